@@ -0,0 +1,73 @@
+use chess_compression::moves::{
+    compress_from, compress_unchecked_from, decompress_from, decompress_unchecked_from,
+};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use shakmaty::{Chess, Move};
+
+#[path = "../src/tests/corpus.rs"]
+mod corpus;
+
+use corpus::{parse, PGNS};
+
+fn bench_compress(c: &mut Criterion) {
+    let games: Vec<Vec<Move>> = PGNS.iter().map(|line| parse(line)).collect();
+
+    let mut group = c.benchmark_group("compress");
+    group.bench_function("checked", |b| {
+        b.iter(|| {
+            for moves in &games {
+                black_box(compress_from(black_box(moves), Chess::default()).unwrap());
+            }
+        })
+    });
+    group.bench_function("unchecked", |b| {
+        b.iter(|| {
+            for moves in &games {
+                black_box(compress_unchecked_from(black_box(moves), Chess::default()).unwrap());
+            }
+        })
+    });
+    group.finish();
+}
+
+fn bench_decompress(c: &mut Criterion) {
+    let games: Vec<(Vec<u8>, i32)> = PGNS
+        .iter()
+        .map(|line| {
+            let moves = parse(line);
+            (
+                compress_from(&moves, Chess::default()).unwrap(),
+                moves.len() as i32,
+            )
+        })
+        .collect();
+
+    let mut group = c.benchmark_group("decompress");
+    group.bench_function("checked", |b| {
+        b.iter(|| {
+            for (bytes, plies) in &games {
+                black_box(
+                    decompress_from(black_box(bytes.as_slice()), *plies, Chess::default()).unwrap(),
+                );
+            }
+        })
+    });
+    group.bench_function("unchecked", |b| {
+        b.iter(|| {
+            for (bytes, plies) in &games {
+                black_box(
+                    decompress_unchecked_from(
+                        black_box(bytes.as_slice()),
+                        *plies,
+                        Chess::default(),
+                    )
+                    .unwrap(),
+                );
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_compress, bench_decompress);
+criterion_main!(benches);
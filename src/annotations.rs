@@ -0,0 +1,441 @@
+//! Functions for compressing and decompressing game annotations: NAGs,
+//! engine evaluations, clock times and text comments.
+//!
+//! Annotations are attached to ply indices of the move sequence compressed
+//! with [`crate::moves::compress`], and stored as a separate blob next to
+//! it. An annotation at ply `n` follows the `n`th move, so ply 0 is a
+//! comment before the first move. Both compression and decompression take
+//! the number of plies in the game and reject annotations past its end, to
+//! keep the two streams in sync.
+//!
+//! The blob is a bit stream with small integers in Elias gamma code. Each
+//! annotation stores the gap from the previous annotated ply and a set of
+//! flags, followed by whichever parts are present:
+//!
+//! - NAGs, using a short Huffman code for the common move and position
+//!   glyphs, with an escape for the rest
+//! - An evaluation in centipawns or moves to mate
+//! - A clock time in centiseconds, as a difference from the previous clock
+//!   time of the same player
+//! - A comment, either as a reference to an identical earlier comment in
+//!   the game or as literal UTF-8 bytes
+//!
+//! [`parse_comment`] and [`format_comment`] convert between PGN comments
+//! with `[%eval ...]` and `[%clk ...]` commands and the structured form.
+
+use crate::bits::{read_gamma, read_signed_gamma, write_gamma, write_signed_gamma};
+pub use crate::eval::Eval;
+use bitbit::{BitReader, BitWriter, MSB};
+use std::fmt::{Display, Formatter};
+use std::io::{Read, Write};
+
+/// Errors that can occur while compressing annotations.
+#[derive(Debug)]
+pub enum CompressError {
+    /// I/O error from the target data sink.
+    IO(std::io::Error),
+    /// Annotation attached to a ply past the end of the game.
+    PlyOutOfRange(u32),
+    /// Annotations are not sorted by strictly increasing ply.
+    Unsorted(u32),
+}
+
+impl From<std::io::Error> for CompressError {
+    fn from(value: std::io::Error) -> Self {
+        Self::IO(value)
+    }
+}
+
+impl std::error::Error for CompressError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        if let Self::IO(e) = self {
+            Some(e)
+        } else {
+            None
+        }
+    }
+}
+
+impl Display for CompressError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CompressError::IO(e) => write!(f, "IO error: {e}"),
+            CompressError::PlyOutOfRange(ply) => {
+                write!(f, "Annotation at ply {ply} is past the end of the game")
+            }
+            CompressError::Unsorted(ply) => write!(f, "Annotation at ply {ply} is out of order"),
+        }
+    }
+}
+
+/// Errors that can occur while decompressing annotations.
+#[derive(Debug)]
+pub enum DecompressError {
+    /// I/O error from the data source.
+    IO(std::io::Error),
+    /// Annotation attached to a ply past the end of the game.
+    PlyOutOfRange(u32),
+    /// Reference to a comment that has not been seen yet.
+    CommentIndex(u32),
+    /// Comment text is not valid UTF-8.
+    Utf8(std::string::FromUtf8Error),
+}
+
+impl From<std::io::Error> for DecompressError {
+    fn from(value: std::io::Error) -> Self {
+        Self::IO(value)
+    }
+}
+
+impl std::error::Error for DecompressError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DecompressError::IO(e) => Some(e),
+            DecompressError::Utf8(e) => Some(e),
+            DecompressError::PlyOutOfRange(_) | DecompressError::CommentIndex(_) => None,
+        }
+    }
+}
+
+impl Display for DecompressError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecompressError::IO(e) => write!(f, "IO error: {e}"),
+            DecompressError::PlyOutOfRange(ply) => {
+                write!(f, "Annotation at ply {ply} is past the end of the game")
+            }
+            DecompressError::CommentIndex(idx) => write!(f, "Unknown comment reference {idx}"),
+            DecompressError::Utf8(e) => write!(f, "UTF-8 error: {e}"),
+        }
+    }
+}
+
+/// Annotations attached to a single ply.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Annotation {
+    /// Number of plies played before the annotation.
+    pub ply: u32,
+    /// Numeric annotation glyphs, such as 1 for `!` and 2 for `?`.
+    pub nags: Vec<u8>,
+    /// Engine evaluation after the move.
+    pub eval: Option<Eval>,
+    /// Remaining clock time of the player who moved, in centiseconds.
+    pub clock: Option<u32>,
+    /// Free text comment.
+    pub comment: Option<String>,
+}
+
+impl Annotation {
+    /// Create an empty annotation for a ply.
+    pub fn new(ply: u32) -> Self {
+        Annotation {
+            ply,
+            ..Default::default()
+        }
+    }
+}
+
+/// Compress annotations for a game with the given number of plies.
+///
+/// The annotations must be sorted by strictly increasing ply, with no ply
+/// greater than `plies`.
+pub fn compress(annotations: &[Annotation], plies: u32) -> Result<Vec<u8>, CompressError> {
+    let mut output = Vec::new();
+    let mut writer = BitWriter::new(&mut output);
+    let mut comments: Vec<&str> = Vec::new();
+    let mut clocks: [Option<u32>; 2] = [None, None];
+
+    write_gamma(annotations.len() as u32, &mut writer)?;
+    let mut previous = None;
+    for annotation in annotations {
+        let ply = annotation.ply;
+        if ply > plies {
+            return Err(CompressError::PlyOutOfRange(ply));
+        }
+        let gap = match previous {
+            None => ply,
+            Some(previous) if ply > previous => ply - previous - 1,
+            Some(_) => return Err(CompressError::Unsorted(ply)),
+        };
+        previous = Some(ply);
+        write_gamma(gap, &mut writer)?;
+
+        writer.write_bit(!annotation.nags.is_empty())?;
+        writer.write_bit(annotation.eval.is_some())?;
+        writer.write_bit(annotation.clock.is_some())?;
+        writer.write_bit(annotation.comment.is_some())?;
+
+        if !annotation.nags.is_empty() {
+            write_gamma(annotation.nags.len() as u32 - 1, &mut writer)?;
+            for nag in &annotation.nags {
+                write_nag(*nag, &mut writer)?;
+            }
+        }
+        if let Some(eval) = annotation.eval {
+            write_eval(eval, &mut writer)?;
+        }
+        if let Some(clock) = annotation.clock {
+            let previous = &mut clocks[(ply % 2) as usize];
+            match previous {
+                Some(previous) => {
+                    write_signed_gamma(clock.wrapping_sub(*previous) as i32, &mut writer)?
+                }
+                None => write_gamma(clock, &mut writer)?,
+            }
+            *previous = Some(clock);
+        }
+        if let Some(comment) = &annotation.comment {
+            if let Some(idx) = comments.iter().position(|c| c == comment) {
+                writer.write_bit(true)?;
+                write_gamma(idx as u32, &mut writer)?;
+            } else {
+                writer.write_bit(false)?;
+                write_gamma(comment.len() as u32, &mut writer)?;
+                for byte in comment.bytes() {
+                    writer.write_byte(byte)?;
+                }
+                comments.push(comment);
+            }
+        }
+    }
+
+    writer.pad_to_byte()?;
+    Ok(output)
+}
+
+/// Decompress annotations for a game with the given number of plies.
+pub fn decompress<R: Read>(input: R, plies: u32) -> Result<Vec<Annotation>, DecompressError> {
+    let mut reader = BitReader::<_, MSB>::new(input);
+    let mut comments: Vec<String> = Vec::new();
+    let mut clocks: [Option<u32>; 2] = [None, None];
+
+    let count = read_gamma(&mut reader)?;
+    let mut annotations = Vec::new();
+    let mut previous: Option<u32> = None;
+    for _i in 0..count {
+        let gap = read_gamma(&mut reader)?;
+        let ply = match previous {
+            None => Some(gap),
+            Some(previous) => previous.checked_add(gap).and_then(|p| p.checked_add(1)),
+        }
+        .unwrap_or(u32::MAX);
+        if ply > plies {
+            return Err(DecompressError::PlyOutOfRange(ply));
+        }
+        previous = Some(ply);
+
+        let has_nags = reader.read_bit()?;
+        let has_eval = reader.read_bit()?;
+        let has_clock = reader.read_bit()?;
+        let has_comment = reader.read_bit()?;
+
+        let mut annotation = Annotation::new(ply);
+        if has_nags {
+            let n = read_gamma(&mut reader)? + 1;
+            for _i in 0..n {
+                annotation.nags.push(read_nag(&mut reader)?);
+            }
+        }
+        if has_eval {
+            annotation.eval = Some(read_eval(&mut reader)?);
+        }
+        if has_clock {
+            let previous = &mut clocks[(ply % 2) as usize];
+            let clock = match previous {
+                Some(previous) => previous.wrapping_add(read_signed_gamma(&mut reader)? as u32),
+                None => read_gamma(&mut reader)?,
+            };
+            *previous = Some(clock);
+            annotation.clock = Some(clock);
+        }
+        if has_comment {
+            let comment = if reader.read_bit()? {
+                let idx = read_gamma(&mut reader)?;
+                comments
+                    .get(idx as usize)
+                    .cloned()
+                    .ok_or(DecompressError::CommentIndex(idx))?
+            } else {
+                let len = read_gamma(&mut reader)?;
+                let mut bytes = Vec::new();
+                for _i in 0..len {
+                    bytes.push(reader.read_byte()?);
+                }
+                let comment = String::from_utf8(bytes).map_err(DecompressError::Utf8)?;
+                comments.push(comment.clone());
+                comment
+            };
+            annotation.comment = Some(comment);
+        }
+        annotations.push(annotation);
+    }
+
+    Ok(annotations)
+}
+
+/// Split a PGN comment into its `[%eval ...]` and `[%clk ...]` commands and
+/// the remaining text, with the annotation's `ply` set to zero.
+///
+/// Other commands, such as `[%csl ...]`, are left in the text, and the text
+/// is `None` if nothing but whitespace remains. So are evaluations that
+/// can't be stored without loss: those with a search depth, such as
+/// `[%eval 0.25,20]`, and those that aren't a finite decimal number of pawns
+/// or `#n` for mate in `n`.
+pub fn parse_comment(comment: &str) -> Annotation {
+    let mut annotation = Annotation::default();
+    let mut text = String::new();
+    let mut rest = comment;
+    while let Some(start) = rest.find("[%") {
+        let Some(len) = rest[start..].find(']') else {
+            break;
+        };
+        let command = &rest[start + 2..start + len];
+        let (name, args) = command.split_once(' ').unwrap_or((command, ""));
+        let parsed = match name {
+            "eval" => parse_eval(args.trim()).map(|eval| annotation.eval = Some(eval)),
+            "clk" => parse_clock(args.trim()).map(|clock| annotation.clock = Some(clock)),
+            _ => None,
+        };
+        let end = start + len + 1;
+        if parsed.is_some() {
+            text.push_str(&rest[..start]);
+        } else {
+            text.push_str(&rest[..end]);
+        }
+        rest = &rest[end..];
+    }
+    text.push_str(rest);
+
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if !text.is_empty() {
+        annotation.comment = Some(text);
+    }
+    annotation
+}
+
+/// Format the evaluation, clock time and comment of an annotation as a PGN
+/// comment, or `None` if it has none of them.
+pub fn format_comment(annotation: &Annotation) -> Option<String> {
+    let mut parts = Vec::new();
+    if let Some(eval) = annotation.eval {
+        parts.push(format!("[%eval {eval}]"));
+    }
+    if let Some(clock) = annotation.clock {
+        let seconds = clock / 100;
+        let mut clk = format!(
+            "[%clk {}:{:02}:{:02}",
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        );
+        if clock % 100 != 0 {
+            let fraction = format!("{:02}", clock % 100);
+            clk.push('.');
+            clk.push_str(fraction.trim_end_matches('0'));
+        }
+        clk.push(']');
+        parts.push(clk);
+    }
+    if let Some(comment) = &annotation.comment {
+        parts.push(comment.clone());
+    }
+    if parts.is_empty() {
+        None
+    } else {
+        Some(parts.join(" "))
+    }
+}
+
+fn parse_eval(s: &str) -> Option<Eval> {
+    if let Some(mate) = s.strip_prefix('#') {
+        return mate.parse().ok().map(Eval::Mate);
+    }
+    /* Only plain decimals, since `f64` also parses `nan`, `inf` and
+     * exponents. */
+    let digits = s.strip_prefix(['-', '+']).unwrap_or(s);
+    let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+    let is_digits = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+    if whole.len() + fraction.len() == 0 || !is_digits(whole) || !is_digits(fraction) {
+        return None;
+    }
+    let centipawns = (s.parse::<f64>().ok()? * 100.0).round();
+    (f64::from(i32::MIN)..=f64::from(i32::MAX))
+        .contains(&centipawns)
+        .then_some(Eval::Centipawns(centipawns as i32))
+}
+
+fn parse_clock(s: &str) -> Option<u32> {
+    let mut seconds = 0.0;
+    for part in s.split(':') {
+        let value: f64 = part.parse().ok()?;
+        seconds = seconds * 60.0 + value;
+    }
+    Some((seconds * 100.0).round() as u32)
+}
+
+/// Prefix code for NAGs, as (NAG, code, length). Codes `110xxx` are the
+/// position assessment glyphs in the table, and `111` is an escape followed
+/// by the raw NAG byte.
+const NAG_CODES: [(u8, u32, usize); 14] = [
+    (2, 0b00, 2),
+    (6, 0b010, 3),
+    (4, 0b011, 3),
+    (1, 0b100, 3),
+    (5, 0b1010, 4),
+    (3, 0b1011, 4),
+    (10, 0b110000, 6),
+    (13, 0b110001, 6),
+    (14, 0b110010, 6),
+    (15, 0b110011, 6),
+    (16, 0b110100, 6),
+    (17, 0b110101, 6),
+    (18, 0b110110, 6),
+    (19, 0b110111, 6),
+];
+
+const NAG_ESCAPE: (u32, usize) = (0b111, 3);
+
+fn write_nag<W: Write>(nag: u8, writer: &mut BitWriter<W>) -> std::io::Result<()> {
+    match NAG_CODES.iter().find(|(n, _, _)| *n == nag) {
+        Some((_, code, bits)) => writer.write_bits(*code, *bits),
+        None => {
+            writer.write_bits(NAG_ESCAPE.0, NAG_ESCAPE.1)?;
+            writer.write_byte(nag)
+        }
+    }
+}
+
+fn read_nag<R: Read>(reader: &mut BitReader<R, MSB>) -> std::io::Result<u8> {
+    let mut code = 0;
+    for bits in 1..=6 {
+        code = (code << 1) | u32::from(reader.read_bit()?);
+        if (code, bits) == NAG_ESCAPE {
+            return reader.read_byte();
+        }
+        if let Some((nag, _, _)) = NAG_CODES.iter().find(|(_, c, b)| *c == code && *b == bits) {
+            return Ok(*nag);
+        }
+    }
+    unreachable!("NAG code table is complete")
+}
+
+fn write_eval<W: Write>(eval: Eval, writer: &mut BitWriter<W>) -> std::io::Result<()> {
+    match eval {
+        Eval::Centipawns(cp) => {
+            writer.write_bit(false)?;
+            write_signed_gamma(cp, writer)
+        }
+        Eval::Mate(n) => {
+            writer.write_bit(true)?;
+            write_signed_gamma(n, writer)
+        }
+    }
+}
+
+fn read_eval<R: Read>(reader: &mut BitReader<R, MSB>) -> std::io::Result<Eval> {
+    if reader.read_bit()? {
+        Ok(Eval::Mate(read_signed_gamma(reader)?))
+    } else {
+        Ok(Eval::Centipawns(read_signed_gamma(reader)?))
+    }
+}
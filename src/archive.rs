@@ -0,0 +1,376 @@
+//! A container format for storing many compressed games in a single file,
+//! with random access by game number.
+//!
+//! An archive is laid out as follows, with all fixed-width integers in
+//! little-endian byte order:
+//!
+//! - The magic bytes `CCAR` and a format version byte
+//! - One record per game: the number of plies in LEB128, the length of the
+//!   starting position in LEB128 followed by the position as compressed by
+//!   [`crate::position::compress`] (a length of zero meaning the standard
+//!   starting position), and the length of the moves in LEB128 followed by
+//!   the moves as compressed by [`crate::moves::compress`]
+//! - An index with the offset of each record from the start of the archive,
+//!   as a 64-bit integer
+//! - A trailer with the number of games as a 64-bit integer, followed by the
+//!   magic bytes `CCIX`
+//!
+//! Since the index comes last, [`ArchiveWriter`] can stream games to its
+//! output without knowing their number in advance. [`ArchiveReader`] reads
+//! the index when opened and then seeks to records as needed.
+
+use crate::{moves, position};
+use shakmaty::{CastlingMode, Chess, EnPassantMode, Move, Position, PositionError, Setup};
+use std::fmt::{Display, Formatter};
+use std::io::{Read, Seek, SeekFrom, Write};
+
+/// Magic bytes at the start of an archive.
+pub const MAGIC: [u8; 4] = *b"CCAR";
+/// Magic bytes at the end of an archive.
+pub const INDEX_MAGIC: [u8; 4] = *b"CCIX";
+/// The format version written by [`ArchiveWriter`].
+pub const VERSION: u8 = 1;
+
+/// Errors that can occur while writing an archive.
+#[derive(Debug)]
+pub enum WriteError {
+    /// I/O error from the target data sink.
+    IO(std::io::Error),
+    /// Error while compressing the starting position of a game.
+    Position(position::CompressError),
+}
+
+impl From<std::io::Error> for WriteError {
+    fn from(value: std::io::Error) -> Self {
+        Self::IO(value)
+    }
+}
+
+impl std::error::Error for WriteError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            WriteError::IO(e) => Some(e),
+            WriteError::Position(e) => Some(e),
+        }
+    }
+}
+
+impl Display for WriteError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WriteError::IO(e) => write!(f, "IO error: {e}"),
+            WriteError::Position(e) => write!(f, "Position compression error: {e}"),
+        }
+    }
+}
+
+/// Errors that can occur while reading an archive.
+#[derive(Debug)]
+pub enum ReadError {
+    /// I/O error from the data source.
+    IO(std::io::Error),
+    /// The input does not start or end with the expected magic bytes.
+    Magic,
+    /// Unsupported format version.
+    Version(u8),
+    /// Error while reading a LEB128 encoded value.
+    Leb128(leb128::read::Error),
+    /// Game number past the end of the archive.
+    Index(usize),
+    /// Count, length or offset out of range for the archive.
+    OutOfRange(u64),
+    /// Error while decompressing the starting position of a game.
+    Position(position::DecompressError),
+    /// The starting position of a game is not a legal chess position.
+    Chess(Box<PositionError<Chess>>),
+    /// Error while decompressing the moves of a game.
+    Moves(moves::DecompressError),
+}
+
+impl From<std::io::Error> for ReadError {
+    fn from(value: std::io::Error) -> Self {
+        Self::IO(value)
+    }
+}
+
+impl From<leb128::read::Error> for ReadError {
+    fn from(value: leb128::read::Error) -> Self {
+        Self::Leb128(value)
+    }
+}
+
+impl std::error::Error for ReadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ReadError::IO(e) => Some(e),
+            ReadError::Leb128(e) => Some(e),
+            ReadError::Position(e) => Some(e),
+            ReadError::Chess(e) => Some(e),
+            ReadError::Moves(e) => Some(e),
+            ReadError::Magic
+            | ReadError::Version(_)
+            | ReadError::Index(_)
+            | ReadError::OutOfRange(_) => None,
+        }
+    }
+}
+
+impl Display for ReadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReadError::IO(e) => write!(f, "IO error: {e}"),
+            ReadError::Magic => write!(f, "Not a game archive"),
+            ReadError::Version(v) => write!(f, "Unsupported archive version {v}"),
+            ReadError::Leb128(e) => write!(f, "Leb128 error: {e}"),
+            ReadError::Index(i) => write!(f, "Game {i} is past the end of the archive"),
+            ReadError::OutOfRange(n) => write!(f, "Value {n} out of range for the archive"),
+            ReadError::Position(e) => write!(f, "Position decompression error: {e}"),
+            ReadError::Chess(e) => write!(f, "Position error: {e}"),
+            ReadError::Moves(e) => write!(f, "Move decompression error: {e}"),
+        }
+    }
+}
+
+/// A single game in an archive.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Game {
+    /// Starting position of the game, or `None` for the standard starting
+    /// position.
+    pub position: Option<Setup>,
+    /// Number of plies in the game.
+    pub plies: u32,
+    /// The moves, as compressed by [`crate::moves::compress`].
+    pub moves: Vec<u8>,
+}
+
+impl Game {
+    /// Compress a game from the standard starting position.
+    pub fn new(moves: &[Move]) -> Result<Self, moves::CompressError> {
+        Ok(Game {
+            position: None,
+            plies: moves.len() as u32,
+            moves: moves::compress(moves)?,
+        })
+    }
+
+    /// Compress a game from a given position.
+    pub fn from_position(moves: &[Move], position: &Chess) -> Result<Self, moves::CompressError> {
+        Ok(Game {
+            position: Some(position.to_setup(EnPassantMode::Legal)),
+            plies: moves.len() as u32,
+            moves: moves::compress_from(moves, position.clone())?,
+        })
+    }
+
+    /// The starting position of the game. The castling mode (standard or
+    /// Chess960) is detected from the position.
+    pub fn start(&self) -> Result<Chess, ReadError> {
+        match &self.position {
+            None => Ok(Chess::default()),
+            Some(setup) => setup
+                .clone()
+                .position(CastlingMode::detect(setup))
+                .map_err(|e| ReadError::Chess(Box::new(e))),
+        }
+    }
+
+    /// Decompress the moves of the game.
+    pub fn decode(&self) -> Result<Vec<Move>, ReadError> {
+        let plies =
+            i32::try_from(self.plies).map_err(|_| ReadError::OutOfRange(self.plies.into()))?;
+        moves::decompress_from(self.moves.as_slice(), plies, self.start()?)
+            .map_err(ReadError::Moves)
+    }
+}
+
+/// Writes games to an archive.
+pub struct ArchiveWriter<W: Write> {
+    output: W,
+    offset: u64,
+    index: Vec<u64>,
+}
+
+impl<W: Write> ArchiveWriter<W> {
+    /// Start a new archive, writing the header to `output`.
+    pub fn new(mut output: W) -> std::io::Result<Self> {
+        output.write_all(&MAGIC)?;
+        output.write_all(&[VERSION])?;
+        Ok(ArchiveWriter {
+            output,
+            offset: MAGIC.len() as u64 + 1,
+            index: Vec::new(),
+        })
+    }
+
+    /// Append a game to the archive.
+    pub fn add(&mut self, game: &Game) -> Result<(), WriteError> {
+        let position = match &game.position {
+            Some(position) => position::compress(position).map_err(WriteError::Position)?,
+            None => Vec::new(),
+        };
+        let mut record = Vec::with_capacity(position.len() + game.moves.len() + 6);
+        leb128::write::unsigned(&mut record, game.plies.into())?;
+        leb128::write::unsigned(&mut record, position.len() as u64)?;
+        record.extend(position);
+        leb128::write::unsigned(&mut record, game.moves.len() as u64)?;
+        record.extend(&game.moves);
+
+        self.output.write_all(&record)?;
+        self.index.push(self.offset);
+        self.offset += record.len() as u64;
+        Ok(())
+    }
+
+    /// Number of games written so far.
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    /// Whether no games have been written yet.
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// Write the index and trailer, returning the underlying output.
+    pub fn finish(mut self) -> std::io::Result<W> {
+        for offset in &self.index {
+            self.output.write_all(&offset.to_le_bytes())?;
+        }
+        self.output
+            .write_all(&(self.index.len() as u64).to_le_bytes())?;
+        self.output.write_all(&INDEX_MAGIC)?;
+        self.output.flush()?;
+        Ok(self.output)
+    }
+}
+
+/// Reads games from an archive.
+pub struct ArchiveReader<R: Read + Seek> {
+    input: R,
+    index: Vec<u64>,
+}
+
+impl<R: Read + Seek> ArchiveReader<R> {
+    /// Open an archive, checking the header and reading the index.
+    pub fn new(mut input: R) -> Result<Self, ReadError> {
+        let mut header = [0; 5];
+        input.seek(SeekFrom::Start(0))?;
+        input.read_exact(&mut header)?;
+        if header[..4] != MAGIC {
+            return Err(ReadError::Magic);
+        }
+        if header[4] != VERSION {
+            return Err(ReadError::Version(header[4]));
+        }
+
+        let end = input.seek(SeekFrom::End(-12))?;
+        let mut trailer = [0; 12];
+        input.read_exact(&mut trailer)?;
+        if trailer[8..] != INDEX_MAGIC {
+            return Err(ReadError::Magic);
+        }
+        let count = u64::from_le_bytes(trailer[..8].try_into().unwrap());
+        let start = count
+            .checked_mul(8)
+            .and_then(|size| end.checked_sub(size))
+            .filter(|start| *start >= header.len() as u64)
+            .ok_or(ReadError::OutOfRange(count))?;
+
+        input.seek(SeekFrom::Start(start))?;
+        let mut bytes = vec![0; (end - start) as usize];
+        input.read_exact(&mut bytes)?;
+        let index = bytes
+            .chunks_exact(8)
+            .map(|chunk| {
+                let offset = u64::from_le_bytes(chunk.try_into().unwrap());
+                if offset < header.len() as u64 || offset >= start {
+                    Err(ReadError::OutOfRange(offset))
+                } else {
+                    Ok(offset)
+                }
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(ArchiveReader { input, index })
+    }
+
+    /// Number of games in the archive.
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    /// Whether the archive contains no games.
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// Read the game with the given number.
+    pub fn game(&mut self, number: usize) -> Result<Game, ReadError> {
+        let offset = *self.index.get(number).ok_or(ReadError::Index(number))?;
+        self.input.seek(SeekFrom::Start(offset))?;
+
+        let plies = leb128::read::unsigned(&mut self.input)?;
+        let plies = u32::try_from(plies).map_err(|_| ReadError::OutOfRange(plies))?;
+        let position = read_bytes(&mut self.input)?;
+        let position = if position.is_empty() {
+            None
+        } else {
+            Some(position::decompress(&position).map_err(ReadError::Position)?)
+        };
+        let moves = read_bytes(&mut self.input)?;
+        Ok(Game {
+            position,
+            plies,
+            moves,
+        })
+    }
+
+    /// Iterate over all games in the archive, in order.
+    pub fn games(&mut self) -> Games<'_, R> {
+        Games {
+            reader: self,
+            next: 0,
+        }
+    }
+
+    /// Return the underlying input.
+    pub fn into_inner(self) -> R {
+        self.input
+    }
+}
+
+/// Iterator over the games in an archive, created by
+/// [`ArchiveReader::games`].
+pub struct Games<'a, R: Read + Seek> {
+    reader: &'a mut ArchiveReader<R>,
+    next: usize,
+}
+
+impl<R: Read + Seek> Iterator for Games<'_, R> {
+    type Item = Result<Game, ReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next >= self.reader.len() {
+            return None;
+        }
+        self.next += 1;
+        Some(self.reader.game(self.next - 1))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.reader.len() - self.next;
+        (remaining, Some(remaining))
+    }
+}
+
+/// Read a LEB128 length-prefixed byte string.
+fn read_bytes<R: Read>(input: &mut R) -> Result<Vec<u8>, ReadError> {
+    let len = leb128::read::unsigned(input)?;
+    /* Read through `take` rather than allocating `len` bytes up front, so a
+     * corrupt length can't make us allocate more than the input holds. */
+    let mut bytes = Vec::new();
+    input.take(len).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 != len {
+        return Err(ReadError::OutOfRange(len));
+    }
+    Ok(bytes)
+}
@@ -0,0 +1,94 @@
+//! Variable-length integer codes on top of [`bitbit`], shared by the codecs
+//! that store auxiliary data next to the move stream.
+
+use bitbit::{BitReader, BitWriter, MSB};
+use std::io::{Read, Result, Write};
+
+/// Write `value` with the Elias gamma code of `value + 1`, so that small
+/// values are cheap: 0 takes one bit, 1-2 take three bits, and so on.
+pub(crate) fn write_gamma<W: Write>(value: u32, writer: &mut BitWriter<W>) -> Result<()> {
+    let value = u64::from(value) + 1;
+    let bits = 64 - value.leading_zeros();
+    for _i in 1..bits {
+        writer.write_bit(false)?;
+    }
+    for i in (0..bits).rev() {
+        writer.write_bit((value >> i) & 1 == 1)?;
+    }
+    Ok(())
+}
+
+/// Number of bits [`write_gamma`] uses for `value`.
+pub(crate) fn gamma_len(value: u32) -> usize {
+    let bits = 64 - (u64::from(value) + 1).leading_zeros() as usize;
+    2 * bits - 1
+}
+
+/// Read a value written by [`write_gamma`].
+pub(crate) fn read_gamma<R: Read>(reader: &mut BitReader<R, MSB>) -> Result<u32> {
+    let mut zeros = 0;
+    while !reader.read_bit()? {
+        zeros += 1;
+        if zeros > 32 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "Gamma code out of range",
+            ));
+        }
+    }
+    let mut value: u64 = 1;
+    for _i in 0..zeros {
+        value = (value << 1) | u64::from(reader.read_bit()?);
+    }
+    u32::try_from(value - 1).map_err(|_| {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, "Gamma code out of range")
+    })
+}
+
+/// Write a signed value with [`write_gamma`], interleaving positive and
+/// negative values (0, -1, 1, -2, ...).
+pub(crate) fn write_signed_gamma<W: Write>(value: i32, writer: &mut BitWriter<W>) -> Result<()> {
+    write_gamma(zigzag(value), writer)
+}
+
+/// Read a value written by [`write_signed_gamma`].
+pub(crate) fn read_signed_gamma<R: Read>(reader: &mut BitReader<R, MSB>) -> Result<i32> {
+    read_gamma(reader).map(unzigzag)
+}
+
+/// Write `value` with the exponential Golomb code of order `k`: the value
+/// shifted right by `k` in gamma code, followed by the `k` low bits. This
+/// suits values that are usually below `2^k` but have a long tail. `k` must
+/// be between 1 and 31.
+pub(crate) fn write_exp_golomb<W: Write>(
+    value: u32,
+    k: usize,
+    writer: &mut BitWriter<W>,
+) -> Result<()> {
+    write_gamma(value >> k, writer)?;
+    writer.write_bits(value & ((1 << k) - 1), k)
+}
+
+/// Read a value written by [`write_exp_golomb`].
+pub(crate) fn read_exp_golomb<R: Read>(reader: &mut BitReader<R, MSB>, k: usize) -> Result<u32> {
+    let high = read_gamma(reader)?;
+    let low = reader.read_bits(k)?;
+    if high > u32::MAX >> k {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "Exponential Golomb code out of range",
+        ));
+    }
+    Ok((high << k) | low)
+}
+
+/// Map a signed value to an unsigned one, interleaving positive and
+/// negative values (0, -1, 1, -2, ...).
+pub(crate) fn zigzag(value: i32) -> u32 {
+    ((value << 1) ^ (value >> 31)) as u32
+}
+
+/// Inverse of [`zigzag`].
+pub(crate) fn unzigzag(value: u32) -> i32 {
+    ((value >> 1) as i32) ^ -((value & 1) as i32)
+}
@@ -0,0 +1,216 @@
+//! A Huffman-coded alternative to the [`crate::position`] format, for large
+//! sets of standard chess positions such as NNUE training data.
+//!
+//! The default format spends 64 bits on the occupancy mask plus four bits per
+//! piece. Here every square instead gets a variable-length code, with short
+//! codes for the common cases: one bit for an empty square, three for a pawn
+//! and five to seven for the other pieces. The special piece values of the
+//! default format (a pawn that just moved two squares, rooks with castling
+//! rights and the black king with black to move) get codes of their own, so
+//! the two formats store exactly the same information. The squares are
+//! followed by a single `0` bit if the clocks have their default values and
+//! the side to move follows from the board, or otherwise a `1` bit, the
+//! halfmove clock and the fullmove number minus one in Elias gamma code, and
+//! a bit that is set if black is to move. The stream is padded to a whole
+//! byte with zero bits.
+//!
+//! The format has no variant information, so as with
+//! [`crate::position::compress`], setups with pockets, promoted pieces or
+//! remaining checks are rejected. Over every position of the games in the
+//! test corpus of this crate it takes about 10% less space than the default
+//! format (121,787 against 134,792 bytes); the start position takes 22 bytes
+//! instead of 24. Use [`crate::position::compress_as`] and
+//! [`crate::position::decompress_as`] to pick a format at run time.
+
+use crate::bits::{gamma_len, read_gamma, write_gamma};
+use crate::position::{
+    self, check_variant_fields, pawn_pushed_to, piece_value, place_value, CompressError, Variant,
+};
+use bitbit::{BitReader, BitWriter, MSB};
+use shakmaty::{Color::Black, Setup, Square};
+use std::fmt::{Display, Formatter};
+use std::num::NonZero;
+
+/// Errors that can occur while decompressing a position.
+#[derive(Debug)]
+pub enum DecompressError {
+    /// I/O error from the data source, including a premature end of input.
+    IO(std::io::Error),
+    /// A reserved code in place of a square.
+    InvalidCode(Square),
+    /// Fullmove number too large for a [`Setup`].
+    Fullmoves(u64),
+    /// The padding bits after the position are not zero.
+    NonZeroPadding,
+    /// Unexpected bytes after the end of the position.
+    TrailingBytes(usize),
+    /// Error while placing a piece.
+    Position(position::DecompressError),
+}
+
+impl From<std::io::Error> for DecompressError {
+    fn from(value: std::io::Error) -> Self {
+        Self::IO(value)
+    }
+}
+
+impl std::error::Error for DecompressError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DecompressError::IO(e) => Some(e),
+            DecompressError::Position(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Display for DecompressError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecompressError::IO(e) => write!(f, "IO error: {e}"),
+            DecompressError::InvalidCode(sq) => write!(f, "Invalid code for square {sq}"),
+            DecompressError::Fullmoves(n) => write!(f, "Fullmove number {n} out of range"),
+            DecompressError::NonZeroPadding => write!(f, "Non-zero padding bits"),
+            DecompressError::TrailingBytes(n) => {
+                write!(f, "{n} trailing bytes after the position")
+            }
+            DecompressError::Position(e) => write!(f, "Position error: {e}"),
+        }
+    }
+}
+
+/// Compress a position. Pockets, promoted pieces and remaining checks can't
+/// be stored and are rejected.
+pub fn compress(position: &Setup) -> Result<Vec<u8>, CompressError> {
+    check_variant_fields(position, Variant::Standard)?;
+    let pawn_pushed_to = pawn_pushed_to(position)?;
+    let black_turn = position.turn == Black;
+    let mut output = Vec::new();
+    let mut writer = BitWriter::new(&mut output);
+
+    for square in Square::ALL {
+        match position.board.piece_at(square) {
+            None => writer.write_bit(false)?,
+            Some(piece) => {
+                let value = piece_value(
+                    piece,
+                    square,
+                    black_turn,
+                    position.castling_rights,
+                    pawn_pushed_to,
+                );
+                let (code, length) = CODES[value as usize];
+                writer.write_bits(code, length)?;
+            }
+        }
+    }
+
+    let broken_turn = black_turn && position.board.king_of(Black).is_none();
+    if position.halfmoves == 0 && position.fullmoves.get() == 1 && !broken_turn {
+        writer.write_bit(false)?;
+    } else {
+        writer.write_bit(true)?;
+        write_gamma(position.halfmoves, &mut writer)?;
+        write_gamma(position.fullmoves.get() - 1, &mut writer)?;
+        writer.write_bit(black_turn)?;
+    }
+    writer.pad_to_byte()?;
+    Ok(output)
+}
+
+/// Decompress a position, rejecting non-zero padding and any bytes after
+/// the end of the encoding.
+pub fn decompress(bytes: &[u8]) -> Result<Setup, DecompressError> {
+    let mut reader = BitReader::<_, MSB>::new(bytes);
+    let mut setup = Setup::empty();
+    let mut bits = 0;
+
+    for square in Square::ALL {
+        let (value, length) = read_value(&mut reader, square)?;
+        bits += length;
+        if let Some(value) = value {
+            place_value(&mut setup, square, value).map_err(DecompressError::Position)?;
+        }
+    }
+
+    bits += 1;
+    if reader.read_bit()? {
+        setup.halfmoves = read_gamma(&mut reader)?;
+        let fullmoves = read_gamma(&mut reader)?;
+        bits += gamma_len(setup.halfmoves) + gamma_len(fullmoves) + 1;
+        setup.fullmoves = fullmoves
+            .checked_add(1)
+            .and_then(NonZero::new)
+            .ok_or(DecompressError::Fullmoves(u64::from(fullmoves) + 1))?;
+        if reader.read_bit()? {
+            setup.turn = Black;
+        }
+    }
+
+    /* The reader only takes a byte from the input once it needs its first
+     * bit, so after the padding the rest of the input is left over. */
+    for _i in 0..(8 - bits % 8) % 8 {
+        if reader.read_bit()? {
+            return Err(DecompressError::NonZeroPadding);
+        }
+    }
+    let trailing = reader.get_ref().len();
+    if trailing > 0 {
+        return Err(DecompressError::TrailingBytes(trailing));
+    }
+    Ok(setup)
+}
+
+/// Read the code for a square, returning the piece value, or `None` for an
+/// empty square, and the length of the code.
+fn read_value(
+    reader: &mut BitReader<&[u8], MSB>,
+    square: Square,
+) -> Result<(Option<u8>, usize), DecompressError> {
+    let mut ones = 0;
+    while reader.read_bit()? {
+        ones += 1;
+        if ones == DECODE.len() {
+            return Err(DecompressError::InvalidCode(square));
+        }
+    }
+    let values = DECODE[ones];
+    if values.is_empty() {
+        return Ok((None, 1));
+    }
+    let suffix = values.len().ilog2() as usize;
+    let index = reader.read_bits(suffix)? as usize;
+    Ok((Some(values[index]), ones + 1 + suffix))
+}
+
+/// The piece values for each code in [`CODES`], indexed by the number of
+/// leading one bits and then by the bits after the first zero.
+const DECODE: [&[u8]; 6] = [
+    &[],
+    &[0, 1],
+    &[2, 3, 4, 5],
+    &[6, 7, 13, 14],
+    &[8, 9, 10, 11],
+    &[12, 15],
+];
+
+/// Codes for the piece values of [`crate::position`], as code and length.
+/// An empty square is `0`, and codes starting with `111111` are reserved.
+const CODES: [(u32, usize); 16] = [
+    (0b100, 3),     // 0: white pawn
+    (0b101, 3),     // 1: black pawn
+    (0b11000, 5),   // 2: white knight
+    (0b11001, 5),   // 3: black knight
+    (0b11010, 5),   // 4: white bishop
+    (0b11011, 5),   // 5: black bishop
+    (0b111000, 6),  // 6: white rook
+    (0b111001, 6),  // 7: black rook
+    (0b1111000, 7), // 8: white queen
+    (0b1111001, 7), // 9: black queen
+    (0b1111010, 7), // 10: white king
+    (0b1111011, 7), // 11: black king
+    (0b1111100, 7), // 12: pawn that just moved two squares
+    (0b111010, 6),  // 13: white rook with castling rights
+    (0b111011, 6),  // 14: black rook with castling rights
+    (0b1111101, 7), // 15: black king with black to move
+];
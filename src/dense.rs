@@ -0,0 +1,273 @@
+//! A densely packed container format for large numbers of games from the
+//! standard starting position.
+//!
+//! Where [`crate::archive`] stores each game as a separate byte string, this
+//! format writes the moves of all games into a single bit stream, so games
+//! don't pay for padding to a byte boundary. That matters for short games:
+//! padding adds 3.5 bits per game on average, which is a noticeable share of
+//! a bullet game of a few dozen moves.
+//!
+//! A dense archive is laid out as follows, with all fixed-width integers in
+//! little-endian byte order:
+//!
+//! - The magic bytes `CCDA` and a format version byte
+//! - The moves of all games, encoded as by [`crate::moves::compress`] and
+//!   padded to a whole byte only at the end
+//! - An index with the number of plies and the number of bits of each game,
+//!   both in LEB128
+//! - A trailer with the offset of the index from the start of the archive and
+//!   the number of games, both as 64-bit integers, followed by the magic
+//!   bytes `CCDX`
+//!
+//! The bit offset of a game is the sum of the lengths of the games before it,
+//! which [`DenseReader`] computes when reading the index. To decode a game,
+//! it seeks to the byte containing the game's first bit and skips the bits
+//! before it.
+
+use crate::archive::ReadError;
+use crate::moves::{read_move, write_move_with_length, CompressError, DecompressError};
+use bitbit::{BitReader, BitWriter, MSB};
+use shakmaty::{Chess, Move, Position};
+use std::io::{Read, Seek, SeekFrom, Write};
+
+/// Magic bytes at the start of a dense archive.
+pub const MAGIC: [u8; 4] = *b"CCDA";
+/// Magic bytes at the end of a dense archive.
+pub const INDEX_MAGIC: [u8; 4] = *b"CCDX";
+/// The format version written by [`DenseWriter`].
+pub const VERSION: u8 = 1;
+
+/// Size of the header in bytes.
+const HEADER_LEN: u64 = 5;
+/// Size of the trailer in bytes.
+const TRAILER_LEN: usize = 20;
+
+/// Writes games to a dense archive.
+///
+/// Each game is passed to the underlying writer in a single write, so it
+/// should usually be buffered.
+pub struct DenseWriter<W: Write> {
+    output: W,
+    /// The bits of the stream that don't fill a whole byte yet, in the high
+    /// bits of `partial`.
+    partial: u8,
+    partial_bits: u8,
+    bits: u64,
+    index: Vec<(u32, u64)>,
+}
+
+impl<W: Write> DenseWriter<W> {
+    /// Start a new dense archive, writing the header to `output`.
+    pub fn new(mut output: W) -> std::io::Result<Self> {
+        output.write_all(&MAGIC)?;
+        output.write_all(&[VERSION])?;
+        Ok(DenseWriter {
+            output,
+            partial: 0,
+            partial_bits: 0,
+            bits: 0,
+            index: Vec::new(),
+        })
+    }
+
+    /// Append a game to the archive.
+    ///
+    /// The game is encoded completely before anything is written, so an
+    /// illegal move leaves the archive as it was.
+    pub fn add(&mut self, moves: &[Move]) -> Result<(), CompressError> {
+        /* Encode into a scratch buffer that starts with the partial byte, so
+         * the game continues the stream where the previous one ended. */
+        let mut buffer = Vec::new();
+        let mut writer = BitWriter::new(&mut buffer);
+        if self.partial_bits > 0 {
+            writer
+                .write_bits(
+                    u32::from(self.partial >> (8 - self.partial_bits)),
+                    self.partial_bits.into(),
+                )
+                .map_err(CompressError::IO)?;
+        }
+        let mut position = Chess::default();
+        let mut bits = 0;
+        for m in moves {
+            bits += u64::from(write_move_with_length(m, &position, &mut writer)?);
+            position = position
+                .play(*m)
+                .map_err(|e| CompressError::Chess(Box::new(e)))?;
+        }
+        writer.pad_to_byte().map_err(CompressError::IO)?;
+
+        let total = u64::from(self.partial_bits) + bits;
+        let (partial_bits, whole) = ((total % 8) as u8, (total / 8) as usize);
+        self.output
+            .write_all(&buffer[..whole])
+            .map_err(CompressError::IO)?;
+        self.partial = if partial_bits > 0 { buffer[whole] } else { 0 };
+        self.partial_bits = partial_bits;
+        self.index.push((moves.len() as u32, bits));
+        self.bits += bits;
+        Ok(())
+    }
+
+    /// Number of games written so far.
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    /// Whether no games have been written yet.
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// Pad the bit stream and write the index and trailer, returning the
+    /// underlying output.
+    pub fn finish(mut self) -> std::io::Result<W> {
+        let mut tail = Vec::new();
+        if self.partial_bits > 0 {
+            tail.push(self.partial);
+        }
+        for (plies, bits) in &self.index {
+            leb128::write::unsigned(&mut tail, (*plies).into())?;
+            leb128::write::unsigned(&mut tail, *bits)?;
+        }
+        tail.extend((HEADER_LEN + self.bits.div_ceil(8)).to_le_bytes());
+        tail.extend((self.index.len() as u64).to_le_bytes());
+        tail.extend(INDEX_MAGIC);
+        self.output.write_all(&tail)?;
+        self.output.flush()?;
+        Ok(self.output)
+    }
+}
+
+/// Reads games from a dense archive.
+pub struct DenseReader<R: Read + Seek> {
+    input: R,
+    /// Number of plies and bit offset of each game.
+    index: Vec<(u32, u64)>,
+}
+
+impl<R: Read + Seek> DenseReader<R> {
+    /// Open a dense archive, checking the header and reading the index.
+    pub fn new(mut input: R) -> Result<Self, ReadError> {
+        let mut header = [0; HEADER_LEN as usize];
+        input.seek(SeekFrom::Start(0))?;
+        input.read_exact(&mut header)?;
+        if header[..4] != MAGIC {
+            return Err(ReadError::Magic);
+        }
+        if header[4] != VERSION {
+            return Err(ReadError::Version(header[4]));
+        }
+
+        let end = input.seek(SeekFrom::End(-(TRAILER_LEN as i64)))?;
+        let mut trailer = [0; TRAILER_LEN];
+        input.read_exact(&mut trailer)?;
+        if trailer[16..] != INDEX_MAGIC {
+            return Err(ReadError::Magic);
+        }
+        let start = u64::from_le_bytes(trailer[..8].try_into().unwrap());
+        let count = u64::from_le_bytes(trailer[8..16].try_into().unwrap());
+        if !(HEADER_LEN..=end).contains(&start) {
+            return Err(ReadError::OutOfRange(start));
+        }
+        /* Every index entry takes at least two bytes. */
+        if count > (end - start) / 2 {
+            return Err(ReadError::OutOfRange(count));
+        }
+
+        input.seek(SeekFrom::Start(start))?;
+        let mut bytes = vec![0; (end - start) as usize];
+        input.read_exact(&mut bytes)?;
+        let mut bytes = bytes.as_slice();
+        let stream_bits = (start - HEADER_LEN) * 8;
+        let mut offset: u64 = 0;
+        let mut index = Vec::with_capacity(count as usize);
+        for _i in 0..count {
+            let plies = leb128::read::unsigned(&mut bytes)?;
+            let plies = u32::try_from(plies).map_err(|_| ReadError::OutOfRange(plies))?;
+            let bits = leb128::read::unsigned(&mut bytes)?;
+            index.push((plies, offset));
+            offset = offset
+                .checked_add(bits)
+                .filter(|end| *end <= stream_bits)
+                .ok_or(ReadError::OutOfRange(bits))?;
+        }
+        Ok(DenseReader { input, index })
+    }
+
+    /// Number of games in the archive.
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    /// Whether the archive contains no games.
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// Number of plies in the game with the given number.
+    pub fn plies(&self, number: usize) -> Result<u32, ReadError> {
+        self.index
+            .get(number)
+            .map(|(plies, _)| *plies)
+            .ok_or(ReadError::Index(number))
+    }
+
+    /// Read the moves of the game with the given number.
+    pub fn game(&mut self, number: usize) -> Result<Vec<Move>, ReadError> {
+        let (plies, offset) = *self.index.get(number).ok_or(ReadError::Index(number))?;
+        self.input.seek(SeekFrom::Start(HEADER_LEN + offset / 8))?;
+        let mut reader = BitReader::<_, MSB>::new(&mut self.input);
+        if offset % 8 != 0 {
+            reader.read_bits((offset % 8) as usize)?;
+        }
+
+        let mut position = Chess::default();
+        let mut moves = Vec::with_capacity(plies.min(1024) as usize);
+        for _i in 0..plies {
+            let m = read_move(&mut reader, &position).map_err(ReadError::Moves)?;
+            position = position
+                .play(m)
+                .map_err(|e| ReadError::Moves(DecompressError::Chess(Box::new(e))))?;
+            moves.push(m);
+        }
+        Ok(moves)
+    }
+
+    /// Iterate over all games in the archive, in order.
+    pub fn games(&mut self) -> Games<'_, R> {
+        Games {
+            reader: self,
+            next: 0,
+        }
+    }
+
+    /// Return the underlying input.
+    pub fn into_inner(self) -> R {
+        self.input
+    }
+}
+
+/// Iterator over the games in a dense archive, created by
+/// [`DenseReader::games`].
+pub struct Games<'a, R: Read + Seek> {
+    reader: &'a mut DenseReader<R>,
+    next: usize,
+}
+
+impl<R: Read + Seek> Iterator for Games<'_, R> {
+    type Item = Result<Vec<Move>, ReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next >= self.reader.len() {
+            return None;
+        }
+        self.next += 1;
+        Some(self.reader.game(self.next - 1))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.reader.len() - self.next;
+        (remaining, Some(remaining))
+    }
+}
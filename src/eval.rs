@@ -0,0 +1,180 @@
+//! Functions for compressing and decompressing sequences of per-ply engine
+//! evaluations.
+//!
+//! The evaluations are stored as a blob next to the move sequence
+//! compressed with [`crate::moves::compress`], with one entry per ply. As
+//! with moves, decompression needs to know the number of plies. Each entry
+//! is a prefix code followed by a value:
+//!
+//! - `0`: centipawn evaluation, as the difference from the previous
+//!   centipawn evaluation in exponential Golomb code of order 4, so that a
+//!   difference between -8 and 7 centipawns takes 6 bits in total, and one
+//!   between -24 and 23 takes 8 bits
+//! - `10`: missing evaluation
+//! - `11`: mate score, as the difference from the previous mate score in
+//!   Elias gamma code, since mate counts usually tick down by one
+//!
+//! Both differences start from zero.
+
+use crate::bits::{
+    read_exp_golomb, read_signed_gamma, unzigzag, write_exp_golomb, write_signed_gamma, zigzag,
+};
+use bitbit::{BitReader, BitWriter, MSB};
+use std::fmt::{Display, Formatter};
+use std::io::{ErrorKind, Read};
+
+/// Order of the exponential Golomb code for centipawn differences.
+const CENTIPAWN_ORDER: usize = 4;
+
+/// Errors that can occur while compressing evaluations.
+#[derive(Debug)]
+pub enum CompressError {
+    /// I/O error from the target data sink.
+    IO(std::io::Error),
+}
+
+impl From<std::io::Error> for CompressError {
+    fn from(value: std::io::Error) -> Self {
+        Self::IO(value)
+    }
+}
+
+impl std::error::Error for CompressError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CompressError::IO(e) => Some(e),
+        }
+    }
+}
+
+impl Display for CompressError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CompressError::IO(e) => write!(f, "IO error: {e}"),
+        }
+    }
+}
+
+/// Errors that can occur while decompressing evaluations.
+#[derive(Debug)]
+pub enum DecompressError {
+    /// I/O error from the data source.
+    IO(std::io::Error),
+    /// The input ended before the given number of plies.
+    MissingBytes,
+    /// A difference code too long for a 32-bit evaluation.
+    OutOfRange,
+}
+
+/* The variable-length codes report truncated input and overlong codes as
+ * I/O errors of these kinds. */
+impl From<std::io::Error> for DecompressError {
+    fn from(value: std::io::Error) -> Self {
+        match value.kind() {
+            ErrorKind::UnexpectedEof => Self::MissingBytes,
+            ErrorKind::InvalidData => Self::OutOfRange,
+            _ => Self::IO(value),
+        }
+    }
+}
+
+impl std::error::Error for DecompressError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DecompressError::IO(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Display for DecompressError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecompressError::IO(e) => write!(f, "IO error: {e}"),
+            DecompressError::MissingBytes => write!(f, "Missing input bytes to decompress"),
+            DecompressError::OutOfRange => write!(f, "Evaluation difference out of range"),
+        }
+    }
+}
+
+/// An engine evaluation, from white's point of view.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Eval {
+    /// Evaluation in centipawns.
+    Centipawns(i32),
+    /// Mate in the given number of moves, negative if black mates.
+    Mate(i32),
+}
+
+impl Display for Eval {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Eval::Centipawns(cp) => {
+                let sign = if *cp < 0 { "-" } else { "" };
+                write!(
+                    f,
+                    "{sign}{}.{:02}",
+                    cp.unsigned_abs() / 100,
+                    cp.unsigned_abs() % 100
+                )
+            }
+            Eval::Mate(n) => write!(f, "#{n}"),
+        }
+    }
+}
+
+/// Compress a sequence of per-ply evaluations, with `None` for plies
+/// without an evaluation.
+pub fn compress(evals: &[Option<Eval>]) -> Result<Vec<u8>, CompressError> {
+    let mut output = Vec::new();
+    let mut writer = BitWriter::new(&mut output);
+    let mut centipawns = 0i32;
+    let mut mate = 0i32;
+
+    for eval in evals {
+        match eval {
+            Some(Eval::Centipawns(cp)) => {
+                writer.write_bit(false)?;
+                write_exp_golomb(
+                    zigzag(cp.wrapping_sub(centipawns)),
+                    CENTIPAWN_ORDER,
+                    &mut writer,
+                )?;
+                centipawns = *cp;
+            }
+            None => writer.write_bits(0b10, 2)?,
+            Some(Eval::Mate(n)) => {
+                writer.write_bits(0b11, 2)?;
+                write_signed_gamma(n.wrapping_sub(mate), &mut writer)?;
+                mate = *n;
+            }
+        }
+    }
+
+    writer.pad_to_byte()?;
+    Ok(output)
+}
+
+/// Decompress a given number of per-ply evaluations.
+pub fn decompress<R: Read>(input: R, plies: usize) -> Result<Vec<Option<Eval>>, DecompressError> {
+    let mut reader = BitReader::<_, MSB>::new(input);
+    let mut centipawns = 0i32;
+    let mut mate = 0i32;
+    let mut evals = Vec::with_capacity(plies);
+
+    for _i in 0..plies {
+        let eval = if !reader.read_bit()? {
+            centipawns =
+                centipawns.wrapping_add(unzigzag(read_exp_golomb(&mut reader, CENTIPAWN_ORDER)?));
+            Some(Eval::Centipawns(centipawns))
+        } else if !reader.read_bit()? {
+            None
+        } else {
+            mate = mate.wrapping_add(read_signed_gamma(&mut reader)?);
+            Some(Eval::Mate(mate))
+        };
+        evals.push(eval);
+    }
+
+    Ok(evals)
+}
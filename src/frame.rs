@@ -0,0 +1,269 @@
+//! Checksummed framing for compressed moves and positions.
+//!
+//! Neither the move nor the position format is self-checking: a flipped bit
+//! in a move blob usually decodes to a different but perfectly legal game.
+//! For long-term storage, a payload can be wrapped in a frame:
+//!
+//! - A format version byte
+//! - A byte giving the kind of payload (see [`Kind`])
+//! - The length of the payload in LEB128
+//! - The payload itself
+//! - The CRC-32 (IEEE) of everything before it, in little-endian byte order
+//!
+//! Frames are self-delimiting, so several can be stored back to back and
+//! checked in bulk with [`verify`]. The `decompress_*` functions check the
+//! checksum before decoding the payload.
+
+use crate::{moves, position};
+use shakmaty::{Chess, Move, Setup};
+use std::fmt::{Display, Formatter};
+
+/// The format version written by this module.
+pub const VERSION: u8 = 1;
+
+/// The kind of payload in a frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    /// Moves compressed with [`crate::moves::compress`].
+    Moves,
+    /// A position compressed with [`crate::position::compress`].
+    Position,
+}
+
+impl Kind {
+    fn to_byte(self) -> u8 {
+        match self {
+            Kind::Moves => 0,
+            Kind::Position => 1,
+        }
+    }
+
+    fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(Kind::Moves),
+            1 => Some(Kind::Position),
+            _ => None,
+        }
+    }
+}
+
+/// Errors that can occur while reading a frame.
+#[derive(Debug)]
+pub enum DecompressError {
+    /// Premature end of input.
+    MissingBytes,
+    /// Unsupported format version.
+    Version(u8),
+    /// Unknown payload kind.
+    Kind(u8),
+    /// The frame holds a different kind of payload than expected.
+    WrongKind(Kind),
+    /// Error while reading a LEB128 encoded value.
+    Leb128(leb128::read::Error),
+    /// The checksum stored in the frame doesn't match its contents.
+    Checksum {
+        /// Checksum stored in the frame.
+        stored: u32,
+        /// Checksum computed from the frame contents.
+        computed: u32,
+    },
+    /// Error while decompressing a move payload.
+    Moves(moves::DecompressError),
+    /// Error while decompressing a position payload.
+    Position(position::DecompressError),
+}
+
+impl From<leb128::read::Error> for DecompressError {
+    fn from(value: leb128::read::Error) -> Self {
+        Self::Leb128(value)
+    }
+}
+
+impl std::error::Error for DecompressError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DecompressError::Leb128(e) => Some(e),
+            DecompressError::Moves(e) => Some(e),
+            DecompressError::Position(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Display for DecompressError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecompressError::MissingBytes => write!(f, "Missing input bytes to decompress"),
+            DecompressError::Version(v) => write!(f, "Unsupported frame version {v}"),
+            DecompressError::Kind(k) => write!(f, "Unknown frame payload kind {k}"),
+            DecompressError::WrongKind(k) => write!(f, "Unexpected frame payload kind {k:?}"),
+            DecompressError::Leb128(e) => write!(f, "Leb128 error: {e}"),
+            DecompressError::Checksum { stored, computed } => write!(
+                f,
+                "Checksum mismatch: stored {stored:08x}, computed {computed:08x}"
+            ),
+            DecompressError::Moves(e) => write!(f, "Move decompression error: {e}"),
+            DecompressError::Position(e) => write!(f, "Position decompression error: {e}"),
+        }
+    }
+}
+
+/// A frame read from a byte slice, after its checksum has been verified.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Frame<'a> {
+    /// The kind of payload.
+    pub kind: Kind,
+    /// The payload.
+    pub payload: &'a [u8],
+}
+
+/// Wrap a payload in a frame.
+pub fn frame(kind: Kind, payload: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(payload.len() + 10);
+    output.push(VERSION);
+    output.push(kind.to_byte());
+    leb128::write::unsigned(&mut output, payload.len() as u64).unwrap();
+    output.extend(payload);
+    let checksum = crc32(&output);
+    output.extend(checksum.to_le_bytes());
+    output
+}
+
+/// Read a frame and verify its checksum, advancing `input` past it.
+pub fn read_frame<'a>(input: &mut &'a [u8]) -> Result<Frame<'a>, DecompressError> {
+    let start = *input;
+    let [version, kind, rest @ ..] = start else {
+        return Err(DecompressError::MissingBytes);
+    };
+    if *version != VERSION {
+        return Err(DecompressError::Version(*version));
+    }
+    let kind = Kind::from_byte(*kind).ok_or(DecompressError::Kind(*kind))?;
+    let mut rest = rest;
+    let len = leb128::read::unsigned(&mut rest)?;
+    let len = usize::try_from(len)
+        .ok()
+        .filter(|len| rest.len().checked_sub(4).is_some_and(|max| *len <= max))
+        .ok_or(DecompressError::MissingBytes)?;
+
+    let header_len = start.len() - rest.len();
+    let (checked, tail) = start.split_at(header_len + len);
+    let stored = u32::from_le_bytes(tail[..4].try_into().unwrap());
+    let computed = crc32(checked);
+    if stored != computed {
+        return Err(DecompressError::Checksum { stored, computed });
+    }
+    *input = &tail[4..];
+    Ok(Frame {
+        kind,
+        payload: &checked[header_len..],
+    })
+}
+
+/// Compress moves from the starting position into a frame.
+pub fn compress_moves(moves: &[Move]) -> Result<Vec<u8>, moves::CompressError> {
+    Ok(frame(Kind::Moves, &moves::compress(moves)?))
+}
+
+/// Compress moves from a given position into a frame.
+pub fn compress_moves_from(
+    moves: &[Move],
+    position: Chess,
+) -> Result<Vec<u8>, moves::CompressError> {
+    Ok(frame(Kind::Moves, &moves::compress_from(moves, position)?))
+}
+
+/// Compress a position into a frame.
+pub fn compress_position(position: &Setup) -> Result<Vec<u8>, position::CompressError> {
+    Ok(frame(Kind::Position, &position::compress(position)?))
+}
+
+/// Verify and decompress a frame holding moves from the starting position.
+pub fn decompress_moves(input: &[u8], plies: i32) -> Result<Vec<Move>, DecompressError> {
+    decompress_moves_from(input, plies, Chess::default())
+}
+
+/// Verify and decompress a frame holding moves from a given position.
+pub fn decompress_moves_from(
+    mut input: &[u8],
+    plies: i32,
+    position: Chess,
+) -> Result<Vec<Move>, DecompressError> {
+    let payload = expect(&mut input, Kind::Moves)?;
+    moves::decompress_from(payload, plies, position).map_err(DecompressError::Moves)
+}
+
+/// Verify and decompress a frame holding a position.
+pub fn decompress_position(mut input: &[u8]) -> Result<Setup, DecompressError> {
+    let payload = expect(&mut input, Kind::Position)?;
+    position::decompress(payload).map_err(DecompressError::Position)
+}
+
+/// Check a sequence of back-to-back frames, returning the index and error
+/// of every frame that fails to verify.
+///
+/// A frame with a bad checksum is reported and skipped. A frame whose header
+/// can't be read ends the scan, since the start of the next frame is then
+/// unknown; an empty result therefore means all frames are intact.
+pub fn verify(mut input: &[u8]) -> Vec<(usize, DecompressError)> {
+    let mut failures = Vec::new();
+    let mut index = 0;
+    while !input.is_empty() {
+        match read_frame(&mut input) {
+            Ok(_) => {}
+            Err(e @ DecompressError::Checksum { .. }) => {
+                failures.push((index, e));
+                skip_frame(&mut input);
+            }
+            Err(e) => {
+                failures.push((index, e));
+                break;
+            }
+        }
+        index += 1;
+    }
+    failures
+}
+
+fn expect<'a>(input: &mut &'a [u8], kind: Kind) -> Result<&'a [u8], DecompressError> {
+    let frame = read_frame(input)?;
+    if frame.kind != kind {
+        return Err(DecompressError::WrongKind(frame.kind));
+    }
+    Ok(frame.payload)
+}
+
+/// Advance past a frame whose header has already been validated by
+/// [`read_frame`].
+fn skip_frame(input: &mut &[u8]) {
+    let mut rest = &input[2..];
+    let len = leb128::read::unsigned(&mut rest).unwrap() as usize;
+    *input = &rest[len + 4..];
+}
+
+/// CRC-32 with the IEEE polynomial, as used by zlib, PNG and many others.
+pub(crate) fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0, |crc, byte| {
+        CRC_TABLE[((crc ^ u32::from(*byte)) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
@@ -0,0 +1,657 @@
+//! Functions for compressing and decompressing game metadata: result,
+//! termination, variant, time control, players, ratings and date.
+//!
+//! The record is meant to be stored alongside the move blob from
+//! [`crate::moves::compress`] and, for games from a non-standard position,
+//! the setup blob from [`crate::position::compress`]. It is laid out as:
+//!
+//! - A flag byte giving which optional fields are present
+//! - A byte with the result in the low two bits and the termination reason
+//!   in the next four
+//! - A byte with the Lichess variant ID
+//! - If present, the time control as an index into a dictionary of common
+//!   time controls, or 255 followed by LEB128 initial time and increment
+//! - If present, the ratings (LEB128) and rating diffs (signed LEB128) of
+//!   white and black
+//! - If present, the date as a LEB128 value `(year * 16 + month) * 32 + day`,
+//!   with zero for unknown parts
+//! - If present, the white and black player IDs as LEB128 length and UTF-8
+//!   bytes
+//!
+//! [`Header::from_tags`] and [`Header::to_tags`] convert to and from the
+//! corresponding PGN tags.
+
+use shakmaty::{Color, KnownOutcome, Outcome};
+use std::fmt::{Display, Formatter};
+
+/// Errors that can occur while compressing a header.
+#[derive(Debug)]
+pub enum CompressError {
+    /// I/O error from the target data sink.
+    IO(std::io::Error),
+    /// Month or day outside the range of a calendar date.
+    Date(Date),
+}
+
+impl From<std::io::Error> for CompressError {
+    fn from(value: std::io::Error) -> Self {
+        Self::IO(value)
+    }
+}
+
+impl std::error::Error for CompressError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CompressError::IO(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Display for CompressError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CompressError::IO(e) => write!(f, "IO error: {e}"),
+            CompressError::Date(date) => write!(f, "Date {date} out of range"),
+        }
+    }
+}
+
+/// Errors that can occur while decompressing a header.
+#[derive(Debug)]
+pub enum DecompressError {
+    /// Premature end of input.
+    MissingBytes,
+    /// Error while reading a LEB128 encoded value.
+    Leb128(leb128::read::Error),
+    /// Unknown termination code.
+    Termination(u8),
+    /// Unknown variant ID.
+    Variant(u8),
+    /// Unknown time control dictionary index.
+    TimeControl(u8),
+    /// Numeric field out of range for its type.
+    OutOfRange(i64),
+    /// Player ID is not valid UTF-8.
+    Utf8(std::string::FromUtf8Error),
+    /// Bytes left over after the last field.
+    TrailingBytes(usize),
+}
+
+impl From<leb128::read::Error> for DecompressError {
+    fn from(value: leb128::read::Error) -> Self {
+        Self::Leb128(value)
+    }
+}
+
+impl std::error::Error for DecompressError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DecompressError::Leb128(e) => Some(e),
+            DecompressError::Utf8(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Display for DecompressError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecompressError::MissingBytes => write!(f, "Missing input bytes to decompress"),
+            DecompressError::Leb128(e) => write!(f, "Leb128 error: {e}"),
+            DecompressError::Termination(code) => write!(f, "Unknown termination code {code}"),
+            DecompressError::Variant(id) => write!(f, "Unknown variant ID {id}"),
+            DecompressError::TimeControl(idx) => write!(f, "Unknown time control index {idx}"),
+            DecompressError::OutOfRange(value) => write!(f, "Value {value} out of range"),
+            DecompressError::Utf8(e) => write!(f, "UTF-8 error: {e}"),
+            DecompressError::TrailingBytes(n) => write!(f, "{n} trailing bytes after header"),
+        }
+    }
+}
+
+/// Reason a game ended, as in the PGN `Termination` tag.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Termination {
+    /// Game ended by the rules, or no reason known.
+    #[default]
+    Normal,
+    /// A player ran out of time.
+    TimeForfeit,
+    /// A player left the game.
+    Abandoned,
+    /// A player was penalised for breaking the rules.
+    RulesInfraction,
+    /// Result decided by a third party.
+    Adjudication,
+    /// Game ended by an emergency.
+    Emergency,
+    /// A player died.
+    Death,
+    /// Game still in progress.
+    Unterminated,
+}
+
+impl Termination {
+    const ALL: [Termination; 8] = [
+        Termination::Normal,
+        Termination::TimeForfeit,
+        Termination::Abandoned,
+        Termination::RulesInfraction,
+        Termination::Adjudication,
+        Termination::Emergency,
+        Termination::Death,
+        Termination::Unterminated,
+    ];
+
+    /// The value of the PGN `Termination` tag.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Termination::Normal => "Normal",
+            Termination::TimeForfeit => "Time forfeit",
+            Termination::Abandoned => "Abandoned",
+            Termination::RulesInfraction => "Rules infraction",
+            Termination::Adjudication => "Adjudication",
+            Termination::Emergency => "Emergency",
+            Termination::Death => "Death",
+            Termination::Unterminated => "Unterminated",
+        }
+    }
+
+    /// Parse the value of a PGN `Termination` tag, ignoring case.
+    pub fn from_tag(value: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|t| t.as_str().eq_ignore_ascii_case(value.trim()))
+    }
+}
+
+/// Chess variant, numbered as by Lichess.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Variant {
+    /// Standard chess.
+    #[default]
+    Standard,
+    /// Chess960.
+    Chess960,
+    /// Standard chess from a custom position.
+    FromPosition,
+    /// King of the hill.
+    KingOfTheHill,
+    /// Three-check.
+    ThreeCheck,
+    /// Antichess.
+    Antichess,
+    /// Atomic chess.
+    Atomic,
+    /// Horde.
+    Horde,
+    /// Racing kings.
+    RacingKings,
+    /// Crazyhouse.
+    Crazyhouse,
+}
+
+impl Variant {
+    const ALL: [Variant; 10] = [
+        Variant::Standard,
+        Variant::Chess960,
+        Variant::FromPosition,
+        Variant::KingOfTheHill,
+        Variant::ThreeCheck,
+        Variant::Antichess,
+        Variant::Atomic,
+        Variant::Horde,
+        Variant::RacingKings,
+        Variant::Crazyhouse,
+    ];
+
+    /// The Lichess variant ID.
+    pub fn id(self) -> u8 {
+        Self::ALL.iter().position(|v| *v == self).unwrap() as u8 + 1
+    }
+
+    /// Look up a variant by Lichess variant ID.
+    pub fn from_id(id: u8) -> Option<Self> {
+        Self::ALL.get(usize::from(id).checked_sub(1)?).copied()
+    }
+
+    /// The value of the PGN `Variant` tag.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Variant::Standard => "Standard",
+            Variant::Chess960 => "Chess960",
+            Variant::FromPosition => "From Position",
+            Variant::KingOfTheHill => "King of the Hill",
+            Variant::ThreeCheck => "Three-check",
+            Variant::Antichess => "Antichess",
+            Variant::Atomic => "Atomic",
+            Variant::Horde => "Horde",
+            Variant::RacingKings => "Racing Kings",
+            Variant::Crazyhouse => "Crazyhouse",
+        }
+    }
+
+    /// Parse the value of a PGN `Variant` tag, ignoring case.
+    pub fn from_tag(value: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|v| v.as_str().eq_ignore_ascii_case(value.trim()))
+    }
+}
+
+/// A time control with initial time and increment, both in seconds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TimeControl {
+    /// Initial time in seconds.
+    pub initial: u32,
+    /// Increment per move in seconds.
+    pub increment: u32,
+}
+
+impl TimeControl {
+    /// Parse the value of a PGN `TimeControl` tag in `initial+increment`
+    /// form.
+    pub fn from_tag(value: &str) -> Option<Self> {
+        let (initial, increment) = value.trim().split_once('+')?;
+        Some(TimeControl {
+            initial: initial.parse().ok()?,
+            increment: increment.parse().ok()?,
+        })
+    }
+}
+
+impl Display for TimeControl {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}+{}", self.initial, self.increment)
+    }
+}
+
+/// Common time controls, encoded as a single byte.
+const TIME_CONTROLS: [(u32, u32); 24] = [
+    (15, 0),
+    (30, 0),
+    (60, 0),
+    (60, 1),
+    (120, 0),
+    (120, 1),
+    (180, 0),
+    (180, 2),
+    (300, 0),
+    (300, 3),
+    (300, 5),
+    (420, 0),
+    (600, 0),
+    (600, 5),
+    (900, 0),
+    (900, 10),
+    (1200, 0),
+    (1500, 0),
+    (1800, 0),
+    (1800, 20),
+    (2700, 15),
+    (3600, 0),
+    (5400, 30),
+    (0, 1),
+];
+
+/// Escape byte for time controls not in [`TIME_CONTROLS`].
+const TIME_CONTROL_ESCAPE: u8 = 255;
+
+/// A date with possibly unknown parts, as in the PGN `Date` tag.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Date {
+    /// Year, if known.
+    pub year: Option<u16>,
+    /// Month from 1 to 12, if known.
+    pub month: Option<u8>,
+    /// Day of the month from 1 to 31, if known.
+    pub day: Option<u8>,
+}
+
+impl Date {
+    /// Parse the value of a PGN `Date` tag, such as `2024.03.??`.
+    pub fn from_tag(value: &str) -> Option<Self> {
+        let mut parts = value.trim().split('.');
+        let year = parse_date_part(parts.next()?)?;
+        let month = parse_date_part(parts.next()?)?;
+        let day = parse_date_part(parts.next()?)?;
+        if parts.next().is_some() {
+            return None;
+        }
+        Some(Date {
+            year,
+            month: month
+                .map(u8::try_from)
+                .transpose()
+                .ok()?
+                .filter(|m| (1..=12).contains(m)),
+            day: day
+                .map(u8::try_from)
+                .transpose()
+                .ok()?
+                .filter(|d| (1..=31).contains(d)),
+        })
+    }
+}
+
+impl Display for Date {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.year {
+            Some(year) => write!(f, "{year:04}.")?,
+            None => write!(f, "????.")?,
+        }
+        match self.month {
+            Some(month) => write!(f, "{month:02}.")?,
+            None => write!(f, "??.")?,
+        }
+        match self.day {
+            Some(day) => write!(f, "{day:02}"),
+            None => write!(f, "??"),
+        }
+    }
+}
+
+fn parse_date_part(part: &str) -> Option<Option<u16>> {
+    if part.chars().all(|c| c == '?') {
+        Some(None)
+    } else {
+        part.parse().ok().map(Some)
+    }
+}
+
+/// Metadata of a game.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Header {
+    /// Result of the game.
+    pub result: Outcome,
+    /// Reason the game ended.
+    pub termination: Termination,
+    /// Variant played.
+    pub variant: Variant,
+    /// Time control, or `None` for unlimited or unknown time.
+    pub time_control: Option<TimeControl>,
+    /// White player ID.
+    pub white: Option<String>,
+    /// Black player ID.
+    pub black: Option<String>,
+    /// White player's rating.
+    pub white_rating: Option<u16>,
+    /// Black player's rating.
+    pub black_rating: Option<u16>,
+    /// Change of the white player's rating from the game.
+    pub white_rating_diff: Option<i16>,
+    /// Change of the black player's rating from the game.
+    pub black_rating_diff: Option<i16>,
+    /// Date the game was played.
+    pub date: Option<Date>,
+}
+
+impl Default for Header {
+    fn default() -> Self {
+        Header {
+            result: Outcome::Unknown,
+            termination: Termination::default(),
+            variant: Variant::default(),
+            time_control: None,
+            white: None,
+            black: None,
+            white_rating: None,
+            black_rating: None,
+            white_rating_diff: None,
+            black_rating_diff: None,
+            date: None,
+        }
+    }
+}
+
+impl Header {
+    /// Build a header from PGN tags, ignoring unknown tags and values that
+    /// cannot be parsed.
+    pub fn from_tags<K: AsRef<str>, V: AsRef<str>>(tags: &[(K, V)]) -> Self {
+        let mut header = Header::default();
+        for (name, value) in tags {
+            let value = value.as_ref();
+            match name.as_ref() {
+                "Result" => header.result = value.parse().unwrap_or(Outcome::Unknown),
+                "Termination" => {
+                    header.termination = Termination::from_tag(value).unwrap_or_default()
+                }
+                "Variant" => header.variant = Variant::from_tag(value).unwrap_or_default(),
+                "TimeControl" => header.time_control = TimeControl::from_tag(value),
+                "White" => header.white = Some(value.to_string()),
+                "Black" => header.black = Some(value.to_string()),
+                "WhiteElo" => header.white_rating = value.parse().ok(),
+                "BlackElo" => header.black_rating = value.parse().ok(),
+                "WhiteRatingDiff" => header.white_rating_diff = value.parse().ok(),
+                "BlackRatingDiff" => header.black_rating_diff = value.parse().ok(),
+                "Date" | "UTCDate" => header.date = Date::from_tag(value),
+                _ => (),
+            }
+        }
+        header
+    }
+
+    /// Convert the header to PGN tags, omitting absent fields.
+    pub fn to_tags(&self) -> Vec<(&'static str, String)> {
+        let mut tags = Vec::new();
+        if let Some(date) = self.date {
+            tags.push(("Date", date.to_string()));
+        }
+        if let Some(white) = &self.white {
+            tags.push(("White", white.clone()));
+        }
+        if let Some(black) = &self.black {
+            tags.push(("Black", black.clone()));
+        }
+        tags.push(("Result", self.result.to_string()));
+        if let Some(rating) = self.white_rating {
+            tags.push(("WhiteElo", rating.to_string()));
+        }
+        if let Some(rating) = self.black_rating {
+            tags.push(("BlackElo", rating.to_string()));
+        }
+        if let Some(diff) = self.white_rating_diff {
+            tags.push(("WhiteRatingDiff", format!("{diff:+}")));
+        }
+        if let Some(diff) = self.black_rating_diff {
+            tags.push(("BlackRatingDiff", format!("{diff:+}")));
+        }
+        if self.variant != Variant::Standard {
+            tags.push(("Variant", self.variant.as_str().to_string()));
+        }
+        if let Some(time_control) = self.time_control {
+            tags.push(("TimeControl", time_control.to_string()));
+        }
+        tags.push(("Termination", self.termination.as_str().to_string()));
+        tags
+    }
+}
+
+const WHITE_RATING: u8 = 1 << 0;
+const BLACK_RATING: u8 = 1 << 1;
+const WHITE_RATING_DIFF: u8 = 1 << 2;
+const BLACK_RATING_DIFF: u8 = 1 << 3;
+const TIME_CONTROL: u8 = 1 << 4;
+const DATE: u8 = 1 << 5;
+const WHITE: u8 = 1 << 6;
+const BLACK: u8 = 1 << 7;
+
+/// Compress a game header. A date with a month outside 1 to 12 or a day
+/// outside 1 to 31 is rejected, since it would not fit its bits.
+pub fn compress(header: &Header) -> Result<Vec<u8>, CompressError> {
+    let mut result = Vec::new();
+
+    let flags = [
+        (header.white_rating.is_some(), WHITE_RATING),
+        (header.black_rating.is_some(), BLACK_RATING),
+        (header.white_rating_diff.is_some(), WHITE_RATING_DIFF),
+        (header.black_rating_diff.is_some(), BLACK_RATING_DIFF),
+        (header.time_control.is_some(), TIME_CONTROL),
+        (header.date.is_some(), DATE),
+        (header.white.is_some(), WHITE),
+        (header.black.is_some(), BLACK),
+    ]
+    .into_iter()
+    .filter(|(present, _)| *present)
+    .fold(0, |flags, (_, flag)| flags | flag);
+    result.push(flags);
+
+    let outcome = match header.result {
+        Outcome::Unknown => 0,
+        Outcome::Known(KnownOutcome::Decisive {
+            winner: Color::White,
+        }) => 1,
+        Outcome::Known(KnownOutcome::Decisive {
+            winner: Color::Black,
+        }) => 2,
+        Outcome::Known(KnownOutcome::Draw) => 3,
+    };
+    let termination = Termination::ALL
+        .iter()
+        .position(|t| *t == header.termination)
+        .unwrap() as u8;
+    result.push(outcome | (termination << 2));
+    result.push(header.variant.id());
+
+    if let Some(tc) = header.time_control {
+        match TIME_CONTROLS
+            .iter()
+            .position(|(initial, increment)| *initial == tc.initial && *increment == tc.increment)
+        {
+            Some(idx) => result.push(idx as u8),
+            None => {
+                result.push(TIME_CONTROL_ESCAPE);
+                leb128::write::unsigned(&mut result, u64::from(tc.initial))?;
+                leb128::write::unsigned(&mut result, u64::from(tc.increment))?;
+            }
+        }
+    }
+    for rating in [header.white_rating, header.black_rating]
+        .into_iter()
+        .flatten()
+    {
+        leb128::write::unsigned(&mut result, u64::from(rating))?;
+    }
+    for diff in [header.white_rating_diff, header.black_rating_diff]
+        .into_iter()
+        .flatten()
+    {
+        leb128::write::signed(&mut result, i64::from(diff))?;
+    }
+    if let Some(date) = header.date {
+        let month_valid = date.month.is_none_or(|m| (1..=12).contains(&m));
+        let day_valid = date.day.is_none_or(|d| (1..=31).contains(&d));
+        if !month_valid || !day_valid {
+            return Err(CompressError::Date(date));
+        }
+        let year = u64::from(date.year.unwrap_or(0));
+        let month = u64::from(date.month.unwrap_or(0));
+        let day = u64::from(date.day.unwrap_or(0));
+        leb128::write::unsigned(&mut result, (year * 16 + month) * 32 + day)?;
+    }
+    for id in [&header.white, &header.black].into_iter().flatten() {
+        leb128::write::unsigned(&mut result, id.len() as u64)?;
+        result.extend(id.as_bytes());
+    }
+
+    Ok(result)
+}
+
+/// Decompress a game header, rejecting any bytes after the last field.
+pub fn decompress(mut bytes: &[u8]) -> Result<Header, DecompressError> {
+    let mut header = Header::default();
+    let head = take(&mut bytes, 3)?;
+    let (flags, outcome, variant) = (head[0], head[1], head[2]);
+
+    header.result = match outcome & 0b11 {
+        0 => Outcome::Unknown,
+        1 => Outcome::Known(KnownOutcome::Decisive {
+            winner: Color::White,
+        }),
+        2 => Outcome::Known(KnownOutcome::Decisive {
+            winner: Color::Black,
+        }),
+        _ => Outcome::Known(KnownOutcome::Draw),
+    };
+    header.termination = *Termination::ALL
+        .get(usize::from(outcome >> 2))
+        .ok_or(DecompressError::Termination(outcome >> 2))?;
+    header.variant = Variant::from_id(variant).ok_or(DecompressError::Variant(variant))?;
+
+    if flags & TIME_CONTROL != 0 {
+        let idx = take(&mut bytes, 1)?[0];
+        header.time_control = Some(if idx == TIME_CONTROL_ESCAPE {
+            TimeControl {
+                initial: read_unsigned(&mut bytes)?,
+                increment: read_unsigned(&mut bytes)?,
+            }
+        } else {
+            let (initial, increment) = *TIME_CONTROLS
+                .get(usize::from(idx))
+                .ok_or(DecompressError::TimeControl(idx))?;
+            TimeControl { initial, increment }
+        });
+    }
+    if flags & WHITE_RATING != 0 {
+        header.white_rating = Some(read_unsigned(&mut bytes)?);
+    }
+    if flags & BLACK_RATING != 0 {
+        header.black_rating = Some(read_unsigned(&mut bytes)?);
+    }
+    if flags & WHITE_RATING_DIFF != 0 {
+        header.white_rating_diff = Some(read_signed(&mut bytes)?);
+    }
+    if flags & BLACK_RATING_DIFF != 0 {
+        header.black_rating_diff = Some(read_signed(&mut bytes)?);
+    }
+    if flags & DATE != 0 {
+        let date: u64 = read_unsigned(&mut bytes)?;
+        let year =
+            u16::try_from(date / 32 / 16).map_err(|_| DecompressError::OutOfRange(date as i64))?;
+        let month = (date / 32 % 16) as u8;
+        if month > 12 {
+            return Err(DecompressError::OutOfRange(i64::from(month)));
+        }
+        let day = (date % 32) as u8;
+        header.date = Some(Date {
+            year: Some(year).filter(|y| *y != 0),
+            month: Some(month).filter(|m| *m != 0),
+            day: Some(day).filter(|d| *d != 0),
+        });
+    }
+    if flags & WHITE != 0 {
+        header.white = Some(read_string(&mut bytes)?);
+    }
+    if flags & BLACK != 0 {
+        header.black = Some(read_string(&mut bytes)?);
+    }
+    if !bytes.is_empty() {
+        return Err(DecompressError::TrailingBytes(bytes.len()));
+    }
+
+    Ok(header)
+}
+
+fn take<'a>(bytes: &mut &'a [u8], n: usize) -> Result<&'a [u8], DecompressError> {
+    if bytes.len() < n {
+        return Err(DecompressError::MissingBytes);
+    }
+    let (head, tail) = bytes.split_at(n);
+    *bytes = tail;
+    Ok(head)
+}
+
+fn read_unsigned<T: TryFrom<u64>>(bytes: &mut &[u8]) -> Result<T, DecompressError> {
+    let value = leb128::read::unsigned(bytes)?;
+    T::try_from(value).map_err(|_| DecompressError::OutOfRange(value as i64))
+}
+
+fn read_signed<T: TryFrom<i64>>(bytes: &mut &[u8]) -> Result<T, DecompressError> {
+    let value = leb128::read::signed(bytes)?;
+    T::try_from(value).map_err(|_| DecompressError::OutOfRange(value))
+}
+
+fn read_string(bytes: &mut &[u8]) -> Result<String, DecompressError> {
+    let len: usize = read_unsigned(bytes)?;
+    String::from_utf8(take(bytes, len)?.to_vec()).map_err(DecompressError::Utf8)
+}
@@ -0,0 +1,59 @@
+//! Canonical keys for positions, for use in opening explorers, transposition
+//! tables and duplicate detection.
+//!
+//! [`crate::position::compress`] stores everything in a [`Setup`], so
+//! positions that are the same for the purpose of a lookup can still compress
+//! differently. A key is a compressed position normalised first:
+//!
+//! - The fullmove number is always dropped, and the halfmove clock unless
+//!   [`KeyOptions::halfmoves`] is set
+//! - The en passant square is dropped unless an en passant capture is
+//!   actually legal
+//! - With [`KeyOptions::white_to_move`], positions with black to move are
+//!   mirrored, so that a position and its colour-flipped twin share a key
+//!
+//! Keys use the same byte layout as [`crate::position`] and decompress with
+//! [`crate::position::decompress`], but they are not meant to be exchanged
+//! with other implementations of that format.
+
+use crate::position::{compress_inline, CompressError, CompressedPosition};
+use shakmaty::{Color, EnPassantMode, Position, Setup};
+use std::num::NonZero;
+
+/// Options for [`key`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct KeyOptions {
+    /// Keep the halfmove clock, for lookups where the fifty-move rule
+    /// matters.
+    pub halfmoves: bool,
+    /// Mirror positions with black to move, swapping the colours of all
+    /// pieces, so that every key has white to move.
+    pub white_to_move: bool,
+}
+
+/// Compute the key for a position.
+///
+/// When [`KeyOptions::white_to_move`] is set, a position with black to move
+/// is mirrored before compressing; check [`Position::turn`] on the original
+/// position to tell whether that happened. As with
+/// [`crate::position::compress`], positions with pockets, promoted pieces or
+/// remaining checks are rejected.
+pub fn key<P: Position>(
+    position: &P,
+    options: KeyOptions,
+) -> Result<CompressedPosition, CompressError> {
+    compress_inline(&normalize(position, options))
+}
+
+/// The normalised setup that [`key`] compresses.
+pub fn normalize<P: Position>(position: &P, options: KeyOptions) -> Setup {
+    let mut setup = position.to_setup(EnPassantMode::Legal);
+    setup.fullmoves = NonZero::<u32>::MIN;
+    if !options.halfmoves {
+        setup.halfmoves = 0;
+    }
+    if options.white_to_move && setup.turn == Color::Black {
+        setup.mirror();
+    }
+    setup
+}
@@ -2,12 +2,43 @@
 //!  ports of [Java](https://github.com/lichess-org/compression/) and
 //! [Scala](https://lichess.org/@/revoof/blog/adapting-nnue-pytorchs-binary-position-format-for-lichess/cpeeAMeY)
 //! originals made by the Lichess project, with some tweaks to the API. The
-//! code is split into two modules, one for compressing moves and one for
-//! positions.
+//! core code is split into two modules, one for compressing moves and one
+//! for positions.
+//!
+//! The remaining modules build on these: [`tree`] handles trees of
+//! variations, [`annotations`] stores NAGs, evaluations, clock times and
+//! comments next to the moves, [`eval`] stores dense per-ply evaluations,
+//! [`pv`] stores engine principal variations, [`header`] stores game
+//! metadata, [`plyset`] stores sparse sets of plies such as draw offers,
+//! [`archive`] and [`dense`] store many games in one file with random
+//! access, [`frame`] adds checksums for long-term storage, [`key`] builds
+//! canonical position keys for lookups, [`compact`] is a smaller position
+//! format for large sets of training positions, [`delta`] stores positions
+//! as changes against a reference position, and with the `pgn` feature
+//! enabled, `pgn` converts compressed games to and from PGN.
 
 pub use shakmaty;
+/* Lets the test corpus shared with the benchmarks name this crate the same
+ * way from both sides. */
+#[cfg(test)]
+extern crate self as chess_compression;
 
+pub mod annotations;
+pub mod archive;
+mod bits;
+pub mod compact;
+pub mod delta;
+pub mod dense;
+pub mod eval;
+pub mod frame;
+pub mod header;
+pub mod key;
 pub mod moves;
+#[cfg(any(feature = "pgn", test))]
+pub mod pgn;
+pub mod plyset;
 pub mod position;
+pub mod pv;
 #[cfg(test)]
 mod tests;
+pub mod tree;
@@ -14,7 +14,11 @@
 use bitbit::{BitReader, BitWriter, MSB};
 use itertools::Itertools;
 use lazy_static::lazy_static;
-use shakmaty::{Chess, Color, Move, Position, Role};
+use shakmaty::san::{ParseSanError, SanError, SanPlus};
+use shakmaty::uci::{IllegalUciMoveError, ParseUciMoveError, UciMove};
+use shakmaty::zobrist::Zobrist64;
+use shakmaty::{Chess, Color, EnPassantMode, Move, Outcome, Position, Role};
+use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
 use std::io::{Read, Write};
 
@@ -27,6 +31,8 @@ pub enum CompressError {
     Chess(Box<shakmaty::PlayError<Chess>>),
     /// Failed to find the move to encode in the list of legal moves in the position.
     MoveNotFound,
+    /// Failed to convert the move notation at the given (zero-based) ply.
+    Notation(usize, NotationError),
 }
 
 impl std::error::Error for CompressError {
@@ -35,6 +41,7 @@ impl std::error::Error for CompressError {
             CompressError::IO(e) => Some(e),
             CompressError::Chess(e) => Some(e),
             CompressError::MoveNotFound => None,
+            CompressError::Notation(_, e) => Some(e),
         }
     }
 }
@@ -45,6 +52,42 @@ impl Display for CompressError {
             CompressError::IO(e) => write!(f, "IO error: {e}"),
             CompressError::Chess(e) => write!(f, "Chess error: {e}"),
             CompressError::MoveNotFound => write!(f, "Move not found in sorted move list"),
+            CompressError::Notation(ply, e) => write!(f, "Notation error at ply {ply}: {e}"),
+        }
+    }
+}
+
+/// Errors that can occur while converting UCI or SAN notation to moves.
+#[derive(Debug)]
+pub enum NotationError {
+    /// Syntactically invalid UCI notation.
+    ParseUci(ParseUciMoveError),
+    /// UCI move that is not legal in the position.
+    IllegalUci(IllegalUciMoveError),
+    /// Syntactically invalid SAN.
+    ParseSan(ParseSanError),
+    /// SAN that is illegal or ambiguous in the position.
+    San(SanError),
+}
+
+impl std::error::Error for NotationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            NotationError::ParseUci(e) => Some(e),
+            NotationError::IllegalUci(e) => Some(e),
+            NotationError::ParseSan(e) => Some(e),
+            NotationError::San(e) => Some(e),
+        }
+    }
+}
+
+impl Display for NotationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NotationError::ParseUci(e) => write!(f, "UCI parse error: {e}"),
+            NotationError::IllegalUci(e) => write!(f, "Illegal UCI move: {e}"),
+            NotationError::ParseSan(e) => write!(f, "SAN parse error: {e}"),
+            NotationError::San(e) => write!(f, "SAN error: {e}"),
         }
     }
 }
@@ -56,6 +99,12 @@ pub enum DecompressError {
     IO(std::io::Error),
     /// Chess logic error while applying a decompressed move to the decoder board state.
     Chess(Box<shakmaty::PlayError<Chess>>),
+    /// Decoded move index is out of range for the legal moves in the position.
+    MoveIndex(u8),
+    /// Padding bits after the last move are not all zero (strict decoding only).
+    NonZeroPadding,
+    /// Input continues after the last move's padding (strict decoding only).
+    TrailingBytes,
 }
 
 impl std::error::Error for DecompressError {
@@ -63,6 +112,9 @@ impl std::error::Error for DecompressError {
         match self {
             DecompressError::IO(e) => Some(e),
             DecompressError::Chess(e) => Some(e),
+            DecompressError::MoveIndex(_)
+            | DecompressError::NonZeroPadding
+            | DecompressError::TrailingBytes => None,
         }
     }
 }
@@ -72,10 +124,53 @@ impl Display for DecompressError {
         match self {
             DecompressError::IO(e) => write!(f, "IO error: {e}"),
             DecompressError::Chess(e) => write!(f, "Chess error: {e}"),
+            DecompressError::MoveIndex(idx) => {
+                write!(f, "Move index {idx} out of range for sorted move list")
+            }
+            DecompressError::NonZeroPadding => write!(f, "Non-zero padding after last move"),
+            DecompressError::TrailingBytes => write!(f, "Trailing bytes after last move"),
         }
     }
 }
 
+/// Summary of the state at the end of a decompressed game.
+///
+/// Returned by [`decompress_with_summary`] and
+/// [`decompress_with_summary_from`], so that callers don't have to replay
+/// the game a second time to find out how it ended.
+#[derive(Clone, Debug)]
+pub struct GameSummary {
+    /// The final position.
+    pub position: Chess,
+    /// Outcome of the final position: checkmate, stalemate, insufficient
+    /// material or [`Outcome::Unknown`] if the game is not over.
+    pub outcome: Outcome,
+    /// Number of times the final position occurred in the game, counting the
+    /// starting position and the final position itself.
+    pub repetitions: u32,
+    /// Highest number of times any single position occurred in the game.
+    pub max_repetitions: u32,
+    /// Halfmove clock of the final position.
+    pub halfmoves: u32,
+}
+
+impl GameSummary {
+    /// Whether the final position occurred at least three times.
+    pub fn is_threefold_repetition(&self) -> bool {
+        self.repetitions >= 3
+    }
+
+    /// Whether a draw can be claimed under the fifty-move rule.
+    pub fn is_fifty_moves(&self) -> bool {
+        self.halfmoves >= 100
+    }
+
+    /// Whether neither side has sufficient material to mate.
+    pub fn is_insufficient_material(&self) -> bool {
+        self.position.is_insufficient_material()
+    }
+}
+
 /* Public API: */
 /// Compress a sequence of moves from the starting position.
 pub fn compress(moves: &[Move]) -> Result<Vec<u8>, CompressError> {
@@ -96,6 +191,84 @@ pub fn compress_from(moves: &[Move], mut position: Chess) -> Result<Vec<u8>, Com
     Ok(output)
 }
 
+/// Compress a sequence of trusted moves from the starting position.
+///
+/// See [`compress_unchecked_from`] for details.
+pub fn compress_unchecked(moves: &[Move]) -> Result<Vec<u8>, CompressError> {
+    compress_unchecked_from(moves, Chess::default())
+}
+
+/// Compress a sequence of trusted moves from a given position.
+///
+/// This produces the same output as [`compress_from`], but skips the
+/// legality check when applying each move to the encoder board state. Each
+/// move is still looked up in the list of legal moves to find its index, so
+/// a move that is not legal results in [`CompressError::MoveNotFound`]
+/// rather than a corrupted board, but no [`CompressError::Chess`] error is
+/// ever returned.
+///
+/// Generating the legal moves dominates the cost of both paths, so the gain
+/// is small: on the games of the test corpus (`cargo bench --features pgn`),
+/// compressing is about 3% faster and decompressing with
+/// [`decompress_unchecked_from`] about 2% faster than the checked versions.
+pub fn compress_unchecked_from(
+    moves: &[Move],
+    mut position: Chess,
+) -> Result<Vec<u8>, CompressError> {
+    let mut output = Vec::new();
+    let mut writer = BitWriter::new(&mut output);
+    for m in moves {
+        write_move(m, &position, &mut writer)?;
+        position.play_unchecked(*m);
+    }
+    writer.pad_to_byte().map_err(CompressError::IO)?;
+    Ok(output)
+}
+
+/// Compress a sequence of moves in UCI notation from the starting position.
+pub fn compress_uci<S: AsRef<str>>(moves: &[S]) -> Result<Vec<u8>, CompressError> {
+    compress_uci_from(moves, Chess::default())
+}
+
+/// Compress a sequence of moves in UCI notation from a given position.
+///
+/// Fails with [`CompressError::Notation`] if a move is unparsable or
+/// illegal in the position it is played from.
+pub fn compress_uci_from<S: AsRef<str>>(
+    moves: &[S],
+    position: Chess,
+) -> Result<Vec<u8>, CompressError> {
+    compress_notation(moves, position, |s, position| {
+        s.parse::<UciMove>()
+            .map_err(NotationError::ParseUci)?
+            .to_move(position)
+            .map_err(NotationError::IllegalUci)
+    })
+}
+
+/// Compress a sequence of moves in SAN from the starting position.
+pub fn compress_san<S: AsRef<str>>(moves: &[S]) -> Result<Vec<u8>, CompressError> {
+    compress_san_from(moves, Chess::default())
+}
+
+/// Compress a sequence of moves in SAN from a given position.
+///
+/// Check and checkmate suffixes are accepted but not verified. Fails with
+/// [`CompressError::Notation`] if a move is unparsable, illegal or ambiguous
+/// in the position it is played from.
+pub fn compress_san_from<S: AsRef<str>>(
+    moves: &[S],
+    position: Chess,
+) -> Result<Vec<u8>, CompressError> {
+    compress_notation(moves, position, |s, position| {
+        s.parse::<SanPlus>()
+            .map_err(NotationError::ParseSan)?
+            .san
+            .to_move(position)
+            .map_err(NotationError::San)
+    })
+}
+
 /// Decompress a given number of moves from the starting position.
 pub fn decompress<R: Read>(input: R, plies: i32) -> Result<Vec<Move>, DecompressError> {
     decompress_from(input, plies, Chess::default())
@@ -109,19 +282,165 @@ pub fn decompress_from<R: Read>(
 ) -> Result<Vec<Move>, DecompressError> {
     let mut reader = BitReader::<_, MSB>::new(input);
     let mut position = position;
+    read_moves(&mut reader, plies, &mut position, |_| ()).map(|(moves, _bits)| moves)
+}
+
+/// Decompress a given number of moves from the starting position, without
+/// revalidating them.
+///
+/// See [`decompress_unchecked_from`] for details.
+pub fn decompress_unchecked<R: Read>(input: R, plies: i32) -> Result<Vec<Move>, DecompressError> {
+    decompress_unchecked_from(input, plies, Chess::default())
+}
+
+/// Decompress a given number of moves from a given position, without
+/// revalidating them.
+///
+/// Every decoded move is taken from the list of legal moves in the current
+/// position, so the legality check done by [`decompress_from`] when
+/// applying it is redundant. This skips it, and so never returns
+/// [`DecompressError::Chess`].
+pub fn decompress_unchecked_from<R: Read>(
+    input: R,
+    plies: i32,
+    mut position: Chess,
+) -> Result<Vec<Move>, DecompressError> {
+    let mut reader = BitReader::<_, MSB>::new(input);
     let mut moves = Vec::new();
 
     for _i in 0..plies {
         let m = read_move(&mut reader, &position)?;
-        position = position
-            .play(m)
-            .map_err(|e| DecompressError::Chess(Box::new(e)))?;
+        position.play_unchecked(m);
         moves.push(m);
     }
 
     Ok(moves)
 }
 
+/// Decompress a given number of moves from the starting position, checking
+/// that the input contains nothing else.
+///
+/// See [`decompress_strict_from`] for details.
+pub fn decompress_strict<R: Read>(input: R, plies: i32) -> Result<Vec<Move>, DecompressError> {
+    decompress_strict_from(input, plies, Chess::default())
+}
+
+/// Decompress a given number of moves from a given position, checking that
+/// the input contains nothing else.
+///
+/// Unlike [`decompress_from`], which ignores anything after the last ply,
+/// this requires that the padding bits after the last move are zero (as
+/// written by [`compress_from`]) and that the input ends right after them.
+/// A blob decoded with the wrong ply count, or with garbage appended, will
+/// usually fail with [`DecompressError::NonZeroPadding`] or
+/// [`DecompressError::TrailingBytes`].
+pub fn decompress_strict_from<R: Read>(
+    input: R,
+    plies: i32,
+    position: Chess,
+) -> Result<Vec<Move>, DecompressError> {
+    let mut reader = BitReader::<_, MSB>::new(input);
+    let mut position = position;
+    let (moves, bits) = read_moves(&mut reader, plies, &mut position, |_| ())?;
+
+    let padding = (8 - bits % 8) % 8;
+    if reader.read_bits(padding).map_err(DecompressError::IO)? != 0 {
+        return Err(DecompressError::NonZeroPadding);
+    }
+    match reader.read_bit() {
+        Ok(_) => Err(DecompressError::TrailingBytes),
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(moves),
+        Err(e) => Err(DecompressError::IO(e)),
+    }
+}
+
+/// Decompress a given number of moves from the starting position, along
+/// with a summary of the final state of the game.
+pub fn decompress_with_summary<R: Read>(
+    input: R,
+    plies: i32,
+) -> Result<(Vec<Move>, GameSummary), DecompressError> {
+    decompress_with_summary_from(input, plies, Chess::default())
+}
+
+/// Decompress a given number of moves from a given position, along with a
+/// summary of the final state of the game.
+///
+/// Repetitions are counted by Zobrist hash, treating positions as equal if
+/// they have the same pieces, side to move, castling rights and legal en
+/// passant captures.
+pub fn decompress_with_summary_from<R: Read>(
+    input: R,
+    plies: i32,
+    position: Chess,
+) -> Result<(Vec<Move>, GameSummary), DecompressError> {
+    let mut reader = BitReader::<_, MSB>::new(input);
+    let mut position = position;
+    let mut seen: HashMap<Zobrist64, u32> = HashMap::new();
+    let mut count = |position: &Chess| {
+        let n = seen
+            .entry(position.zobrist_hash(EnPassantMode::Legal))
+            .or_insert(0);
+        *n += 1;
+        *n
+    };
+
+    let mut repetitions = count(&position);
+    let mut max_repetitions = repetitions;
+    let (moves, _bits) = read_moves(&mut reader, plies, &mut position, |position| {
+        repetitions = count(position);
+        max_repetitions = max_repetitions.max(repetitions);
+    })?;
+
+    let summary = GameSummary {
+        outcome: position.outcome(),
+        halfmoves: position.halfmoves(),
+        position,
+        repetitions,
+        max_repetitions,
+    };
+    Ok((moves, summary))
+}
+
+/// Decompress a given number of moves from the starting position to UCI
+/// notation.
+pub fn decompress_to_uci<R: Read>(input: R, plies: i32) -> Result<Vec<String>, DecompressError> {
+    decompress_to_uci_from(input, plies, Chess::default())
+}
+
+/// Decompress a given number of moves from a given position to UCI notation.
+///
+/// Castling moves are written as king-to-rook moves if the position uses
+/// Chess960 castling, and as standard king moves otherwise.
+pub fn decompress_to_uci_from<R: Read>(
+    input: R,
+    plies: i32,
+    position: Chess,
+) -> Result<Vec<String>, DecompressError> {
+    let mode = position.castles().mode();
+    decompress_notation(input, plies, position, |m, position| {
+        position.play_unchecked(m);
+        m.to_uci(mode).to_string()
+    })
+}
+
+/// Decompress a given number of moves from the starting position to SAN.
+pub fn decompress_to_san<R: Read>(input: R, plies: i32) -> Result<Vec<String>, DecompressError> {
+    decompress_to_san_from(input, plies, Chess::default())
+}
+
+/// Decompress a given number of moves from a given position to SAN,
+/// including check and checkmate suffixes.
+pub fn decompress_to_san_from<R: Read>(
+    input: R,
+    plies: i32,
+    position: Chess,
+) -> Result<Vec<String>, DecompressError> {
+    decompress_notation(input, plies, position, |m, position| {
+        SanPlus::from_move_and_play_unchecked(position, m).to_string()
+    })
+}
+
 /// Low-level function writing a single compressed move to a [`BitWriter`].
 ///
 /// Remember that the writer buffers partially-written bytes, so your output
@@ -132,27 +451,108 @@ pub fn write_move<W: Write>(
     position: &Chess,
     writer: &mut BitWriter<W>,
 ) -> Result<(), CompressError> {
+    write_move_with_length(m, position, writer).map(|_bits| ())
+}
+
+/// Low-level function that reads a single move from a [`BitReader`].
+pub fn read_move<R: Read>(
+    reader: &mut BitReader<R, MSB>,
+    position: &Chess,
+) -> Result<Move, DecompressError> {
+    read_move_with_length(reader, position).map(|(m, _bits)| m)
+}
+
+/* Internal API implementing the compression: */
+
+fn compress_notation<S: AsRef<str>>(
+    moves: &[S],
+    mut position: Chess,
+    parse: impl Fn(&str, &Chess) -> Result<Move, NotationError>,
+) -> Result<Vec<u8>, CompressError> {
+    let mut output = Vec::new();
+    let mut writer = BitWriter::new(&mut output);
+    for (ply, s) in moves.iter().enumerate() {
+        let m = parse(s.as_ref(), &position).map_err(|e| CompressError::Notation(ply, e))?;
+        write_move(&m, &position, &mut writer)?;
+        position.play_unchecked(m);
+    }
+    writer.pad_to_byte().map_err(CompressError::IO)?;
+    Ok(output)
+}
+
+/// Read `plies` moves, formatting each with `format`, which is also
+/// responsible for playing the move on the position.
+fn decompress_notation<R: Read>(
+    input: R,
+    plies: i32,
+    mut position: Chess,
+    format: impl Fn(Move, &mut Chess) -> String,
+) -> Result<Vec<String>, DecompressError> {
+    let mut reader = BitReader::<_, MSB>::new(input);
+    let mut moves = Vec::new();
+
+    for _i in 0..plies {
+        let m = read_move(&mut reader, &position)?;
+        moves.push(format(m, &mut position));
+    }
+
+    Ok(moves)
+}
+
+/// Read `plies` moves, returning them along with the number of bits read.
+/// `position` is left at the final position, and `after_ply` is called with
+/// each position reached along the way.
+fn read_moves<R: Read>(
+    reader: &mut BitReader<R, MSB>,
+    plies: i32,
+    position: &mut Chess,
+    mut after_ply: impl FnMut(&Chess),
+) -> Result<(Vec<Move>, usize), DecompressError> {
+    let mut moves = Vec::new();
+    let mut bits = 0;
+
+    for _i in 0..plies {
+        let (m, length) = read_move_with_length(reader, position)?;
+        *position = std::mem::take(position)
+            .play(m)
+            .map_err(|e| DecompressError::Chess(Box::new(e)))?;
+        after_ply(position);
+        moves.push(m);
+        bits += length as usize;
+    }
+
+    Ok((moves, bits))
+}
+
+/// Write a single move, returning the number of bits written.
+pub(crate) fn write_move_with_length<W: Write>(
+    m: &Move,
+    position: &Chess,
+    writer: &mut BitWriter<W>,
+) -> Result<u8, CompressError> {
     let moves = sorted_moves(position);
     let idx = moves.into_iter().position(|r| r == *m);
     if let Some(idx) = idx {
-        write(idx as u8, writer)
+        write(idx as u8, writer)?;
+        Ok(CODES[idx].1)
     } else {
         Err(CompressError::MoveNotFound)
     }
 }
 
-/// Low-level function that reads a single move from a [`BitReader`].
-pub fn read_move<R: Read>(
+fn read_move_with_length<R: Read>(
     reader: &mut BitReader<R, MSB>,
     position: &Chess,
-) -> Result<Move, DecompressError> {
+) -> Result<(Move, u8), DecompressError> {
     let idx = read(reader)?;
     let moves = sorted_moves(position);
-    Ok(moves[idx as usize].clone())
+    let m = moves
+        .get(idx as usize)
+        .copied()
+        .ok_or(DecompressError::MoveIndex(idx))?;
+    Ok((m, CODES[idx as usize].1))
 }
 
-/* Internal API implementing the compression: */
-
 struct Symbol(u32, u8);
 
 enum Node {
@@ -0,0 +1,361 @@
+//! Functions for converting compressed games to and from PGN.
+//!
+//! This module is only available with the `pgn` feature enabled. The writer
+//! decodes a move blob produced by [`crate::moves::compress`] (optionally
+//! from a non-standard starting position) and emits a PGN document with the
+//! given tags, SAN moves with check and checkmate suffixes, and a result
+//! token.
+//!
+//! In the other direction, [`CompressingVisitor`] is a [`pgn_reader`]
+//! visitor that compresses each game's mainline as it is read, so large PGN
+//! files can be converted one game at a time:
+//!
+//! ```no_run
+//! use chess_compression::pgn::{pgn_reader::Reader, CompressingVisitor};
+//!
+//! let file = std::fs::File::open("games.pgn").unwrap();
+//! let mut reader = Reader::new(file);
+//! for game in reader.read_games(&mut CompressingVisitor) {
+//!     match game.unwrap() {
+//!         Ok(game) => println!("{} plies in {} bytes", game.plies, game.moves.len()),
+//!         Err(e) => eprintln!("Skipping game: {e}"),
+//!     }
+//! }
+//! ```
+
+use crate::header::Variant;
+use crate::moves::{read_move, write_move, CompressError, DecompressError};
+use bitbit::{BitReader, BitWriter, MSB};
+pub use pgn_reader;
+use pgn_reader::{RawTag, SanPlus, Skip, Visitor};
+use shakmaty::fen::{Fen, ParseFenError};
+use shakmaty::san::SanError;
+use shakmaty::{CastlingMode, Chess, Color, EnPassantMode, Position, PositionError};
+use std::fmt::{Display, Formatter};
+use std::io::{Read, Write};
+use std::ops::ControlFlow;
+
+/// Maximum line length of the movetext, as recommended by the PGN standard.
+const LINE_LENGTH: usize = 79;
+
+/// Errors that can occur while writing a PGN document.
+#[derive(Debug)]
+pub enum WriteError {
+    /// I/O error from the target data sink.
+    IO(std::io::Error),
+    /// Error while decompressing the moves of the game.
+    Decompress(DecompressError),
+}
+
+impl From<std::io::Error> for WriteError {
+    fn from(value: std::io::Error) -> Self {
+        Self::IO(value)
+    }
+}
+
+impl From<DecompressError> for WriteError {
+    fn from(value: DecompressError) -> Self {
+        Self::Decompress(value)
+    }
+}
+
+impl std::error::Error for WriteError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            WriteError::IO(e) => Some(e),
+            WriteError::Decompress(e) => Some(e),
+        }
+    }
+}
+
+impl Display for WriteError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WriteError::IO(e) => write!(f, "IO error: {e}"),
+            WriteError::Decompress(e) => write!(f, "Decompression error: {e}"),
+        }
+    }
+}
+
+/// Errors that can occur while compressing a game read from PGN.
+#[derive(Debug)]
+pub enum ReadError {
+    /// The `Variant` tag names a variant other than standard chess or
+    /// Chess960, whose rules the move encoder doesn't follow.
+    Variant(String),
+    /// The `FEN` tag could not be parsed.
+    Fen(ParseFenError),
+    /// The `FEN` tag describes an illegal position.
+    Position(Box<PositionError<Chess>>),
+    /// The move at the given (zero-based) ply is illegal or ambiguous.
+    San(usize, SanError),
+    /// Error while compressing a move.
+    Compress(CompressError),
+}
+
+impl std::error::Error for ReadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ReadError::Variant(_) => None,
+            ReadError::Fen(e) => Some(e),
+            ReadError::Position(e) => Some(e),
+            ReadError::San(_, e) => Some(e),
+            ReadError::Compress(e) => Some(e),
+        }
+    }
+}
+
+impl Display for ReadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReadError::Variant(v) => write!(f, "Unsupported variant: {v}"),
+            ReadError::Fen(e) => write!(f, "FEN error: {e}"),
+            ReadError::Position(e) => write!(f, "Position error: {e}"),
+            ReadError::San(ply, e) => write!(f, "SAN error at ply {ply}: {e}"),
+            ReadError::Compress(e) => write!(f, "Compression error: {e}"),
+        }
+    }
+}
+
+/// A game compressed by [`CompressingVisitor`].
+#[derive(Clone, Debug)]
+pub struct CompressedGame {
+    /// Tags of the game, in the order they were read.
+    pub tags: Vec<(String, String)>,
+    /// Starting position of the game, if given by a `FEN` tag.
+    pub position: Option<Chess>,
+    /// Number of plies in the mainline.
+    pub plies: i32,
+    /// Mainline moves compressed as by [`crate::moves::compress_from`].
+    pub moves: Vec<u8>,
+}
+
+/// A [`pgn_reader::Visitor`] that compresses the mainline of each game.
+///
+/// Tags are collected as strings, and a `FEN` tag (with or without `SetUp`)
+/// sets the starting position, using Chess960 castling if the `Variant` tag
+/// says so. Games in other variants are rejected. Variations are skipped. A
+/// game with an illegal move, bad `FEN` tag or unsupported variant produces
+/// a [`ReadError`] and does not affect the following games.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CompressingVisitor;
+
+/// Per-game state of [`CompressingVisitor`].
+pub struct Movetext {
+    tags: Vec<(String, String)>,
+    start: Option<Chess>,
+    position: Chess,
+    plies: i32,
+    writer: BitWriter<Vec<u8>>,
+}
+
+impl Visitor for CompressingVisitor {
+    type Tags = Vec<(String, String)>;
+    type Movetext = Movetext;
+    type Output = Result<CompressedGame, ReadError>;
+
+    fn begin_tags(&mut self) -> ControlFlow<Self::Output, Self::Tags> {
+        ControlFlow::Continue(Vec::new())
+    }
+
+    fn tag(
+        &mut self,
+        tags: &mut Self::Tags,
+        name: &[u8],
+        value: RawTag<'_>,
+    ) -> ControlFlow<Self::Output> {
+        tags.push((
+            String::from_utf8_lossy(name).into_owned(),
+            value.decode_utf8_lossy().into_owned(),
+        ));
+        ControlFlow::Continue(())
+    }
+
+    fn begin_movetext(&mut self, tags: Self::Tags) -> ControlFlow<Self::Output, Self::Movetext> {
+        let start = match start_position(&tags) {
+            Ok(start) => start,
+            Err(e) => return ControlFlow::Break(Err(e)),
+        };
+        ControlFlow::Continue(Movetext {
+            position: start.clone().unwrap_or_default(),
+            tags,
+            start,
+            plies: 0,
+            writer: BitWriter::new(Vec::new()),
+        })
+    }
+
+    fn san(&mut self, movetext: &mut Self::Movetext, san: SanPlus) -> ControlFlow<Self::Output> {
+        let ply = movetext.plies as usize;
+        let result = san
+            .san
+            .to_move(&movetext.position)
+            .map_err(|e| ReadError::San(ply, e))
+            .and_then(|m| {
+                write_move(&m, &movetext.position, &mut movetext.writer)
+                    .map_err(ReadError::Compress)?;
+                Ok(m)
+            });
+        match result {
+            Ok(m) => {
+                movetext.position.play_unchecked(m);
+                movetext.plies += 1;
+                ControlFlow::Continue(())
+            }
+            Err(e) => ControlFlow::Break(Err(e)),
+        }
+    }
+
+    fn begin_variation(
+        &mut self,
+        _movetext: &mut Self::Movetext,
+    ) -> ControlFlow<Self::Output, Skip> {
+        ControlFlow::Continue(Skip(true))
+    }
+
+    fn end_game(&mut self, mut movetext: Self::Movetext) -> Self::Output {
+        movetext
+            .writer
+            .pad_to_byte()
+            .map_err(|e| ReadError::Compress(CompressError::IO(e)))?;
+        Ok(CompressedGame {
+            tags: movetext.tags,
+            position: movetext.start,
+            plies: movetext.plies,
+            moves: movetext.writer.get_ref().clone(),
+        })
+    }
+}
+
+fn start_position(tags: &[(String, String)]) -> Result<Option<Chess>, ReadError> {
+    let mut mode = CastlingMode::Standard;
+    if let Some((_, value)) = tags.iter().find(|(name, _)| name == "Variant") {
+        if value.to_lowercase().starts_with("chess960") {
+            mode = CastlingMode::Chess960;
+        } else if !matches!(
+            Variant::from_tag(value),
+            Some(Variant::Standard | Variant::FromPosition)
+        ) {
+            return Err(ReadError::Variant(value.clone()));
+        }
+    }
+    let Some((_, fen)) = tags.iter().find(|(name, _)| name == "FEN") else {
+        return Ok(None);
+    };
+    Fen::from_ascii(fen.as_bytes())
+        .map_err(ReadError::Fen)?
+        .into_position(mode)
+        .map(Some)
+        .map_err(|e| ReadError::Position(Box::new(e)))
+}
+
+/// Write a PGN document for a given number of compressed moves from the
+/// starting position.
+///
+/// See [`write_pgn_from`] for details.
+pub fn write_pgn<R: Read, W: Write>(
+    output: W,
+    input: R,
+    plies: i32,
+    tags: &[(&str, &str)],
+) -> Result<(), WriteError> {
+    write_pgn_from(output, input, plies, Chess::default(), tags)
+}
+
+/// Write a PGN document for a given number of compressed moves from a given
+/// position.
+///
+/// Tags are written in the order given. If the position is not the standard
+/// starting position, `SetUp` and `FEN` tags are added unless already
+/// present. If there is no `Result` tag, one is added with the outcome of
+/// the final position (checkmate, stalemate or insufficient material), or
+/// `*` if the game is not over. The movetext ends with the same result.
+pub fn write_pgn_from<R: Read, W: Write>(
+    mut output: W,
+    input: R,
+    plies: i32,
+    position: Chess,
+    tags: &[(&str, &str)],
+) -> Result<(), WriteError> {
+    let has_tag = |name: &str| tags.iter().any(|(n, _)| *n == name);
+
+    let mut reader = BitReader::<_, MSB>::new(input);
+    let mut current = position.clone();
+    let mut tokens = Vec::new();
+    for i in 0..plies {
+        let m = read_move(&mut reader, &current)?;
+        if current.turn() == Color::White {
+            tokens.push(format!("{}.", current.fullmoves()));
+        } else if i == 0 {
+            tokens.push(format!("{}...", current.fullmoves()));
+        }
+        tokens.push(SanPlus::from_move_and_play_unchecked(&mut current, m).to_string());
+    }
+
+    let result = match tags.iter().find(|(name, _)| *name == "Result") {
+        Some((_, value)) => value.to_string(),
+        None => current.outcome().to_string(),
+    };
+
+    for (name, value) in tags {
+        write_tag(&mut output, name, value)?;
+    }
+    if position != Chess::default() {
+        if !has_tag("SetUp") {
+            write_tag(&mut output, "SetUp", "1")?;
+        }
+        if !has_tag("FEN") {
+            let fen = Fen::from_position(&position, EnPassantMode::Legal).to_string();
+            write_tag(&mut output, "FEN", &fen)?;
+        }
+    }
+    if !has_tag("Result") {
+        write_tag(&mut output, "Result", &result)?;
+    }
+    writeln!(output)?;
+
+    tokens.push(result);
+    let mut line_length = 0;
+    for token in tokens {
+        if line_length > 0 && line_length + 1 + token.len() > LINE_LENGTH {
+            writeln!(output)?;
+            line_length = 0;
+        }
+        if line_length > 0 {
+            write!(output, " ")?;
+            line_length += 1;
+        }
+        write!(output, "{token}")?;
+        line_length += token.len();
+    }
+    writeln!(output)?;
+    writeln!(output)?;
+
+    Ok(())
+}
+
+/// Write a PGN document for a given number of compressed moves from the
+/// starting position to a string.
+pub fn to_pgn<R: Read>(input: R, plies: i32, tags: &[(&str, &str)]) -> Result<String, WriteError> {
+    to_pgn_from(input, plies, Chess::default(), tags)
+}
+
+/// Write a PGN document for a given number of compressed moves from a given
+/// position to a string.
+pub fn to_pgn_from<R: Read>(
+    input: R,
+    plies: i32,
+    position: Chess,
+    tags: &[(&str, &str)],
+) -> Result<String, WriteError> {
+    let mut output = Vec::new();
+    write_pgn_from(&mut output, input, plies, position, tags)?;
+    /* We only ever write valid UTF-8, since tags are given as strings. */
+    Ok(String::from_utf8(output).unwrap())
+}
+
+fn write_tag<W: Write>(output: &mut W, name: &str, value: &str) -> Result<(), WriteError> {
+    let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+    writeln!(output, "[{name} \"{value}\"]")?;
+    Ok(())
+}
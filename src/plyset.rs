@@ -0,0 +1,194 @@
+//! Functions for compressing and decompressing sparse sets of plies, such
+//! as the plies with draw offers, berserked moves or premoves in a game.
+//!
+//! A ply set is a sorted list of zero-based indices into the move sequence
+//! compressed with [`crate::moves::compress`], and is stored as a separate
+//! blob next to it. As with moves, decompression needs to know the number
+//! of plies in the game. The first bit selects between two encodings,
+//! whichever is smaller:
+//!
+//! - `0`: the number of plies in the set, then the gap before each ply, all
+//!   in Elias gamma code
+//! - `1`: a bitmap with one bit per ply in the game
+//!
+//! [`to_flags`], [`from_flags`] and [`select`] line a set up with the
+//! decoded moves.
+
+use crate::bits::{gamma_len, read_gamma, write_gamma};
+use bitbit::{BitReader, BitWriter, MSB};
+use std::fmt::{Display, Formatter};
+use std::io::Read;
+
+/// Errors that can occur while compressing a ply set.
+#[derive(Debug)]
+pub enum CompressError {
+    /// I/O error from the target data sink.
+    IO(std::io::Error),
+    /// Ply index past the end of the game.
+    PlyOutOfRange(u32),
+    /// Plies are not sorted in strictly increasing order.
+    Unsorted(u32),
+}
+
+impl From<std::io::Error> for CompressError {
+    fn from(value: std::io::Error) -> Self {
+        Self::IO(value)
+    }
+}
+
+impl std::error::Error for CompressError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        if let Self::IO(e) = self {
+            Some(e)
+        } else {
+            None
+        }
+    }
+}
+
+impl Display for CompressError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CompressError::IO(e) => write!(f, "IO error: {e}"),
+            CompressError::PlyOutOfRange(ply) => {
+                write!(f, "Ply {ply} is past the end of the game")
+            }
+            CompressError::Unsorted(ply) => write!(f, "Ply {ply} is out of order"),
+        }
+    }
+}
+
+/// Errors that can occur while decompressing a ply set.
+#[derive(Debug)]
+pub enum DecompressError {
+    /// I/O error from the data source.
+    IO(std::io::Error),
+    /// Ply index past the end of the game.
+    PlyOutOfRange(u32),
+}
+
+impl From<std::io::Error> for DecompressError {
+    fn from(value: std::io::Error) -> Self {
+        Self::IO(value)
+    }
+}
+
+impl std::error::Error for DecompressError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        if let Self::IO(e) = self {
+            Some(e)
+        } else {
+            None
+        }
+    }
+}
+
+impl Display for DecompressError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecompressError::IO(e) => write!(f, "IO error: {e}"),
+            DecompressError::PlyOutOfRange(ply) => {
+                write!(f, "Ply {ply} is past the end of the game")
+            }
+        }
+    }
+}
+
+/// Compress a set of ply indices for a game with the given number of plies.
+///
+/// The indices must be sorted in strictly increasing order and less than
+/// `plies`.
+pub fn compress(set: &[u32], plies: u32) -> Result<Vec<u8>, CompressError> {
+    let mut gaps = Vec::with_capacity(set.len());
+    let mut previous = None;
+    for &ply in set {
+        if ply >= plies {
+            return Err(CompressError::PlyOutOfRange(ply));
+        }
+        gaps.push(match previous {
+            None => ply,
+            Some(previous) if ply > previous => ply - previous - 1,
+            Some(_) => return Err(CompressError::Unsorted(ply)),
+        });
+        previous = Some(ply);
+    }
+
+    let mut output = Vec::new();
+    let mut writer = BitWriter::new(&mut output);
+    let gap_bits = gamma_len(set.len() as u32) + gaps.iter().map(|g| gamma_len(*g)).sum::<usize>();
+    if gap_bits <= plies as usize {
+        writer.write_bit(false)?;
+        write_gamma(set.len() as u32, &mut writer)?;
+        for gap in gaps {
+            write_gamma(gap, &mut writer)?;
+        }
+    } else {
+        writer.write_bit(true)?;
+        for flag in to_flags(set, plies as usize) {
+            writer.write_bit(flag)?;
+        }
+    }
+    writer.pad_to_byte()?;
+    Ok(output)
+}
+
+/// Decompress a set of ply indices for a game with the given number of
+/// plies.
+pub fn decompress<R: Read>(input: R, plies: u32) -> Result<Vec<u32>, DecompressError> {
+    let mut reader = BitReader::<_, MSB>::new(input);
+    let mut set = Vec::new();
+    if reader.read_bit()? {
+        for ply in 0..plies {
+            if reader.read_bit()? {
+                set.push(ply);
+            }
+        }
+    } else {
+        let count = read_gamma(&mut reader)?;
+        let mut previous: Option<u32> = None;
+        for _i in 0..count {
+            let gap = read_gamma(&mut reader)?;
+            let ply = match previous {
+                None => Some(gap),
+                Some(previous) => previous.checked_add(gap).and_then(|p| p.checked_add(1)),
+            }
+            .unwrap_or(u32::MAX);
+            if ply >= plies {
+                return Err(DecompressError::PlyOutOfRange(ply));
+            }
+            set.push(ply);
+            previous = Some(ply);
+        }
+    }
+    Ok(set)
+}
+
+/// Convert a ply set to one flag per ply, for a game with the given number
+/// of plies. Indices past the end are ignored.
+pub fn to_flags(set: &[u32], plies: usize) -> Vec<bool> {
+    let mut flags = vec![false; plies];
+    for &ply in set {
+        if let Some(flag) = flags.get_mut(ply as usize) {
+            *flag = true;
+        }
+    }
+    flags
+}
+
+/// Convert one flag per ply to a ply set.
+pub fn from_flags(flags: &[bool]) -> Vec<u32> {
+    flags
+        .iter()
+        .enumerate()
+        .filter(|(_, flag)| **flag)
+        .map(|(ply, _)| ply as u32)
+        .collect()
+}
+
+/// Pick out the items, such as decoded moves, at the plies in a set, along
+/// with their ply indices. Indices past the end are ignored.
+pub fn select<'a, T>(items: &'a [T], set: &[u32]) -> Vec<(u32, &'a T)> {
+    set.iter()
+        .filter_map(|&ply| items.get(ply as usize).map(|item| (ply, item)))
+        .collect()
+}
@@ -10,20 +10,51 @@
 //! - A sequence of bytes encoding the pieces on squares, two per byte.
 //!   Special values encode castling rights, en passant and side to move.
 //! - Optionally, LEB128-encoded halfmove clock and number of plies played
+//! - For variants other than standard chess, a byte giving the variant,
+//!   followed by the remaining checks for three-check (two nibbles, white's
+//!   in the low nibble) or the pockets for crazyhouse (one byte per role from
+//!   pawn to queen, with white's count in the low nibble, then the 64-bit BE
+//!   bitboard of promoted pieces if there are any)
+//!
+//! Since the trailer is optional, the end of an encoding is only known from
+//! the end of the input. To store many positions in one stream, use
+//! [`compress_into`] and [`decompress_from`], which either prefix each
+//! position with its length or always write the trailer (see [`Framing`]).
+//!
+//! Decoding rejects halfmove clocks and ply counts that don't fit a
+//! [`Setup`], as well as any bytes after the end of the encoding.
+//!
+//! Standard chess positions don't carry a variant byte, so [`compress`] and
+//! [`compress_variant`] with [`Variant::Standard`] produce the same output.
+//! The variant bytes follow upstream: 0 for standard chess, 1 crazyhouse,
+//! 2 Chess960, 3 from position, 4 king of the hill, 5 three-check,
+//! 6 antichess, 7 atomic, 8 horde and 9 racing kings.
+//!
+//! For large sets of standard chess positions, [`crate::compact`] offers a
+//! smaller format; [`Format`] selects between the two.
 //!
 //! [blog post]: https://lichess.org/@/revoof/blog/adapting-nnue-pytorchs-binary-position-format-for-lichess/cpeeAMeY
 //! [code]: https://github.com/lichess-org/scalachess/blob/master/core/src/main/scala/format/BinaryFen.scala
 
 use shakmaty::{
-    Bitboard,
+    fen::{Fen, LossyFenErrorKinds, ParseFenError},
+    Bitboard, ByColor, ByRole, CastlingMode, Chess, Color,
     Color::{Black, White},
-    Piece,
+    EnPassantMode, FromSetup, Piece, Position, PositionError, Rank, RemainingChecks,
     Role::*,
     Setup, Square,
 };
 use std::fmt::{Display, Formatter};
+use std::io::{Read, Write};
 use std::num::NonZero;
 
+pub use crate::header::Variant;
+
+/// Upper bound on the length of a position encoding: the occupancy mask, a
+/// nibble for each of up to 64 pieces, the halfmove clock and ply count,
+/// the variant byte, and crazyhouse pockets and promoted pieces.
+pub const MAX_LEN: usize = 8 + 32 + 5 + 5 + 1 + 13;
+
 /// Errors that can occur while compressing a position.
 #[derive(Debug)]
 pub enum CompressError {
@@ -31,6 +62,15 @@ pub enum CompressError {
     IO(std::io::Error),
     /// Attempt to offset a square out of the chess board.
     SquareOffset(Square, i32),
+    /// More than 15 pieces of a kind in a crazyhouse pocket, or a king in
+    /// one.
+    Pocket(Piece, u8),
+    /// Pockets or promoted pieces in a variant other than crazyhouse.
+    Pockets(Variant),
+    /// Promoted pieces on empty squares.
+    Promoted(Bitboard),
+    /// Remaining checks in a variant other than three-check.
+    RemainingChecks(Variant),
 }
 
 impl From<std::io::Error> for CompressError {
@@ -56,6 +96,16 @@ impl Display for CompressError {
             CompressError::SquareOffset(sq, i) => {
                 write!(f, "Attempted to offset {sq} by {i} out of the board")
             }
+            CompressError::Pocket(piece, n) => {
+                write!(f, "Invalid pocket: {n} of {piece:?}")
+            }
+            CompressError::Pockets(v) => write!(f, "Pockets in variant {}", v.as_str()),
+            CompressError::Promoted(squares) => {
+                write!(f, "Promoted pieces on empty squares: {squares:?}")
+            }
+            CompressError::RemainingChecks(v) => {
+                write!(f, "Remaining checks in variant {}", v.as_str())
+            }
         }
     }
 }
@@ -69,6 +119,45 @@ pub enum DecompressError {
     SquareOffset(Square, i32),
     /// Error while reading a LEB128 encoded value.
     Leb128(leb128::read::Error),
+    /// I/O error from the data source.
+    IO(std::io::Error),
+    /// Length prefix longer than any position encoding.
+    Length(u64),
+    /// Halfmove clock too large for a [`Setup`].
+    Halfmoves(u64),
+    /// Ply count too large for the fullmove number of a [`Setup`].
+    PlyCount(u64),
+    /// Unexpected bytes after the end of the position.
+    TrailingBytes(usize),
+    /// Unknown variant byte.
+    Variant(u8),
+    /// Remaining checks out of range for three-check.
+    RemainingChecks(u8),
+    /// Promoted pieces on empty squares.
+    Promoted(Bitboard),
+    /// More than 32 occupied squares (strict mode only).
+    TooManyPieces(u32),
+    /// A second pawn marked as just pushed two squares (strict mode only).
+    DuplicateEnPassant(Square),
+    /// A pawn marked as just pushed two squares outside the fourth and fifth
+    /// ranks (strict mode only).
+    EnPassantRank(Square),
+    /// A pawn marked as just pushed two squares by the side to move (strict
+    /// mode only).
+    EnPassantTurn(Square),
+    /// A rook with castling rights off its back rank (strict mode only).
+    CastlingRook(Square),
+    /// A second black king marked as having the move (strict mode only).
+    DuplicateTurnMarker(Square),
+    /// The ply count says white is to move, but the black king is marked as
+    /// having the move (strict mode only).
+    TurnMismatch,
+    /// The unused half of the last piece byte is not zero (strict mode only).
+    NonZeroPadding,
+    /// The position is in a variant that isn't standard chess.
+    WrongVariant(Variant),
+    /// The position is not a legal chess position.
+    Position(Box<PositionError<Chess>>),
 }
 
 impl From<leb128::read::Error> for DecompressError {
@@ -77,12 +166,19 @@ impl From<leb128::read::Error> for DecompressError {
     }
 }
 
+impl From<std::io::Error> for DecompressError {
+    fn from(value: std::io::Error) -> Self {
+        Self::IO(value)
+    }
+}
+
 impl std::error::Error for DecompressError {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
-        if let Self::Leb128(e) = self {
-            Some(e)
-        } else {
-            None
+        match self {
+            DecompressError::IO(e) => Some(e),
+            DecompressError::Leb128(e) => Some(e),
+            DecompressError::Position(e) => Some(e),
+            _ => None,
         }
     }
 }
@@ -95,30 +191,549 @@ impl Display for DecompressError {
                 write!(f, "Attempted to offset {sq} by {i} out of the board")
             }
             DecompressError::Leb128(e) => write!(f, "Leb128 error: {e}"),
+            DecompressError::IO(e) => write!(f, "IO error: {e}"),
+            DecompressError::Length(n) => write!(f, "Position length {n} out of range"),
+            DecompressError::Halfmoves(n) => write!(f, "Halfmove clock {n} out of range"),
+            DecompressError::PlyCount(n) => write!(f, "Ply count {n} out of range"),
+            DecompressError::TrailingBytes(n) => {
+                write!(f, "{n} trailing bytes after the position")
+            }
+            DecompressError::Variant(v) => write!(f, "Unknown variant {v}"),
+            DecompressError::RemainingChecks(n) => {
+                write!(f, "Remaining checks {n} out of range")
+            }
+            DecompressError::Promoted(squares) => {
+                write!(f, "Promoted pieces on empty squares: {squares:?}")
+            }
+            DecompressError::TooManyPieces(n) => write!(f, "Too many pieces: {n}"),
+            DecompressError::DuplicateEnPassant(sq) => {
+                write!(f, "Second en passant pawn on {sq}")
+            }
+            DecompressError::EnPassantRank(sq) => {
+                write!(f, "En passant pawn on {sq} is on the wrong rank")
+            }
+            DecompressError::EnPassantTurn(sq) => {
+                write!(f, "En passant pawn on {sq} belongs to the side to move")
+            }
+            DecompressError::CastlingRook(sq) => {
+                write!(f, "Castling rook on {sq} is off the back rank")
+            }
+            DecompressError::DuplicateTurnMarker(sq) => {
+                write!(f, "Second black king to move on {sq}")
+            }
+            DecompressError::TurnMismatch => {
+                write!(f, "Ply count and black king disagree on side to move")
+            }
+            DecompressError::NonZeroPadding => write!(f, "Non-zero padding nibble"),
+            DecompressError::WrongVariant(v) => write!(f, "Not a chess position: {}", v.as_str()),
+            DecompressError::Position(e) => write!(f, "Position error: {e}"),
+        }
+    }
+}
+
+/// Errors from the conversions between compressed positions and FENs or
+/// [`Position`]s, covering both the codec and the conversion itself. `P` is
+/// the position type of [`decompress_position`].
+#[derive(Debug)]
+pub enum ConvertError<P = Chess> {
+    /// The FEN could not be parsed.
+    Fen(ParseFenError),
+    /// The decompressed position can't be represented exactly as a FEN.
+    LossyFen(LossyFenErrorKinds),
+    /// The decompressed position is not legal for the requested type. As
+    /// with [`DecompressError::Position`], the error can be used to recover
+    /// the position with the `ignore_*` methods of [`PositionError`].
+    Illegal(Box<PositionError<P>>),
+    /// Error while compressing the position.
+    Compress(CompressError),
+    /// Error while decompressing the position.
+    Decompress(DecompressError),
+    /// Error while decompressing a position in the [`Format::Compact`] format.
+    Compact(crate::compact::DecompressError),
+}
+
+impl<P> From<ParseFenError> for ConvertError<P> {
+    fn from(value: ParseFenError) -> Self {
+        Self::Fen(value)
+    }
+}
+
+impl<P> From<CompressError> for ConvertError<P> {
+    fn from(value: CompressError) -> Self {
+        Self::Compress(value)
+    }
+}
+
+impl<P> From<DecompressError> for ConvertError<P> {
+    fn from(value: DecompressError) -> Self {
+        Self::Decompress(value)
+    }
+}
+
+impl<P> From<crate::compact::DecompressError> for ConvertError<P> {
+    fn from(value: crate::compact::DecompressError) -> Self {
+        Self::Compact(value)
+    }
+}
+
+impl<P: std::fmt::Debug + 'static> std::error::Error for ConvertError<P> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConvertError::Fen(e) => Some(e),
+            ConvertError::Compress(e) => Some(e),
+            ConvertError::Decompress(e) => Some(e),
+            ConvertError::Compact(e) => Some(e),
+            ConvertError::Illegal(e) => Some(e.as_ref()),
+            ConvertError::LossyFen(_) => None,
         }
     }
 }
 
-/// Compress a position.
+impl<P> Display for ConvertError<P> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConvertError::Fen(e) => write!(f, "FEN error: {e}"),
+            ConvertError::LossyFen(kinds) => write!(f, "Not representable as FEN: {kinds:?}"),
+            ConvertError::Illegal(e) => write!(f, "Illegal position: {e}"),
+            ConvertError::Compress(e) => write!(f, "Position compression error: {e}"),
+            ConvertError::Decompress(e) => write!(f, "Position decompression error: {e}"),
+            ConvertError::Compact(e) => write!(f, "Compact position decompression error: {e}"),
+        }
+    }
+}
+
+/// Compress a standard chess position.
+///
+/// Setups with pockets, promoted pieces or remaining checks are rejected;
+/// use [`compress_variant`] to store them.
 pub fn compress(position: &Setup) -> Result<Vec<u8>, CompressError> {
-    let mut result = Vec::new();
+    compress_variant(position, Variant::Standard)
+}
+
+/// Compress a position in the given variant.
+///
+/// For crazyhouse the pockets and promoted pieces are stored, and for
+/// three-check the remaining checks, defaulting to empty pockets and three
+/// remaining checks if the setup doesn't have them. As upstream, pockets and
+/// promoted pieces in other variants, remaining checks outside three-check,
+/// kings in pockets and promoted pieces on empty squares are rejected.
+pub fn compress_variant(position: &Setup, variant: Variant) -> Result<Vec<u8>, CompressError> {
+    compress_variant_inline(position, variant).map(|compressed| compressed.as_ref().to_vec())
+}
+
+/// Compress a standard chess position without allocating, as
+/// [`compress`].
+pub fn compress_inline(position: &Setup) -> Result<CompressedPosition, CompressError> {
+    compress_variant_inline(position, Variant::Standard)
+}
+
+/// Compress a position in the given variant without allocating, as
+/// [`compress_variant`].
+pub fn compress_variant_inline(
+    position: &Setup,
+    variant: Variant,
+) -> Result<CompressedPosition, CompressError> {
+    encode(position, variant, false)
+}
+
+/// A compressed position stored inline, without a heap allocation.
+///
+/// Comparison and hashing work on the encoded bytes, the same as for the
+/// byte slice returned by [`AsRef::as_ref`], so a map keyed by
+/// `CompressedPosition` can also be looked up with a `&[u8]`.
+#[derive(Clone, Copy)]
+pub struct CompressedPosition {
+    bytes: [u8; MAX_LEN],
+    len: u8,
+}
+
+impl CompressedPosition {
+    /// A view for querying the position without decoding it.
+    pub fn view(&self) -> CompressedPositionView<'_> {
+        CompressedPositionView::new(self.as_ref()).expect("encoder output is complete")
+    }
+
+    fn push(&mut self, byte: u8) {
+        self.bytes[self.len as usize] = byte;
+        self.len += 1;
+    }
+
+    fn extend(&mut self, bytes: impl IntoIterator<Item = u8>) {
+        for byte in bytes {
+            self.push(byte);
+        }
+    }
+
+    fn write_leb128(&mut self, value: u64) -> std::io::Result<()> {
+        let written = leb128::write::unsigned(&mut &mut self.bytes[self.len as usize..], value)?;
+        self.len += written as u8;
+        Ok(())
+    }
+}
+
+impl Default for CompressedPosition {
+    fn default() -> Self {
+        CompressedPosition {
+            bytes: [0; MAX_LEN],
+            len: 0,
+        }
+    }
+}
+
+impl AsRef<[u8]> for CompressedPosition {
+    fn as_ref(&self) -> &[u8] {
+        &self.bytes[..self.len as usize]
+    }
+}
+
+impl std::borrow::Borrow<[u8]> for CompressedPosition {
+    fn borrow(&self) -> &[u8] {
+        self.as_ref()
+    }
+}
+
+impl std::fmt::Debug for CompressedPosition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("CompressedPosition")
+            .field(&self.as_ref())
+            .finish()
+    }
+}
+
+impl PartialEq for CompressedPosition {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_ref() == other.as_ref()
+    }
+}
+
+impl Eq for CompressedPosition {}
+
+impl PartialOrd for CompressedPosition {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for CompressedPosition {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.as_ref().cmp(other.as_ref())
+    }
+}
+
+impl std::hash::Hash for CompressedPosition {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.as_ref().hash(state)
+    }
+}
+
+/// Compress a position given as a FEN.
+pub fn compress_fen(fen: &str) -> Result<Vec<u8>, ConvertError> {
+    let setup = Fen::from_ascii(fen.as_bytes())?.into_setup();
+    Ok(compress(&setup)?)
+}
+
+/// Decompress a position to a FEN.
+pub fn decompress_to_fen(bytes: &[u8]) -> Result<String, ConvertError> {
+    let fen =
+        Fen::try_from_setup(decompress(bytes)?).map_err(|e| ConvertError::LossyFen(e.kinds()))?;
+    Ok(fen.to_string())
+}
+
+/// Compress a playable position, such as a [`Chess`].
+pub fn compress_position<P: Position>(position: &P) -> Result<Vec<u8>, ConvertError> {
+    Ok(compress(&position.to_setup(EnPassantMode::Legal))?)
+}
+
+/// Decompress a position and validate it as a playable position of type
+/// `P` with the given castling mode.
+pub fn decompress_position<P: FromSetup>(
+    bytes: &[u8],
+    mode: CastlingMode,
+) -> Result<P, ConvertError<P>> {
+    P::from_setup(decompress(bytes)?, mode).map_err(|e| ConvertError::Illegal(Box::new(e)))
+}
+
+/// A position format.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Format {
+    /// The format of this module, which also stores variants.
+    #[default]
+    BinaryFen,
+    /// The smaller format of [`crate::compact`], for standard chess only.
+    Compact,
+}
+
+/// Compress a standard chess position in the given format.
+pub fn compress_as(position: &Setup, format: Format) -> Result<Vec<u8>, CompressError> {
+    match format {
+        Format::BinaryFen => compress(position),
+        Format::Compact => crate::compact::compress(position),
+    }
+}
+
+/// Decompress a standard chess position in the given format.
+pub fn decompress_as(bytes: &[u8], format: Format) -> Result<Setup, ConvertError> {
+    Ok(match format {
+        Format::BinaryFen => decompress(bytes)?,
+        Format::Compact => crate::compact::decompress(bytes)?,
+    })
+}
+
+/// A view of a compressed position that answers queries straight from the
+/// encoded bytes, without building a [`Setup`].
+///
+/// Looking up the piece on a square decodes a single nibble, and queries
+/// about the whole board scan the nibbles without allocating. The halfmove
+/// clock, ply count and variant are only parsed by the methods that need
+/// them, so those methods can fail on a malformed trailer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CompressedPositionView<'a> {
+    bytes: &'a [u8],
+    occupied: Bitboard,
+}
+
+impl<'a> CompressedPositionView<'a> {
+    /// Create a view of a compressed position, checking that the occupancy
+    /// mask and piece bytes are present.
+    pub fn new(bytes: &'a [u8]) -> Result<Self, DecompressError> {
+        let occupied = Bitboard(u64::from_be_bytes(
+            bytes
+                .get(0..8)
+                .ok_or(DecompressError::MissingBytes)?
+                .try_into()
+                .unwrap(),
+        ));
+        if bytes.len() < 8 + occupied.count().div_ceil(2) {
+            return Err(DecompressError::MissingBytes);
+        }
+        Ok(CompressedPositionView { bytes, occupied })
+    }
+
+    /// The underlying bytes.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    /// The occupied squares.
+    pub fn occupied(&self) -> Bitboard {
+        self.occupied
+    }
+
+    /// The piece on a square, if any.
+    pub fn piece_at(&self, square: Square) -> Option<Piece> {
+        if !self.occupied.contains(square) {
+            return None;
+        }
+        let below = Bitboard::from(square).0 - 1;
+        let index = (self.occupied.0 & below).count_ones() as usize;
+        Some(piece_from_value(self.value(index), square))
+    }
+
+    /// The number of pieces of each kind on the board.
+    pub fn material(&self) -> ByColor<ByRole<u8>> {
+        let mut material = ByColor::<ByRole<u8>>::default();
+        for (square, value) in self.values() {
+            let piece = piece_from_value(value, square);
+            *material.get_mut(piece.color).get_mut(piece.role) += 1;
+        }
+        material
+    }
+
+    /// The rooks with castling rights.
+    pub fn castling_rights(&self) -> Bitboard {
+        self.values()
+            .filter(|(_, value)| *value == 13 || *value == 14)
+            .map(|(square, _)| square)
+            .collect()
+    }
+
+    /// The en passant square, if a pawn is marked as just pushed two
+    /// squares. As with [`decompress`], the last such pawn wins.
+    pub fn ep_square(&self) -> Option<Square> {
+        let (square, _) = self.values().filter(|(_, value)| *value == 12).last()?;
+        let offset = if Bitboard::SOUTH.contains(square) {
+            -8
+        } else {
+            8
+        };
+        square.offset(offset)
+    }
+
+    /// The side to move.
+    pub fn turn(&self) -> Result<Color, DecompressError> {
+        if self.values().any(|(_, value)| value == 15) {
+            return Ok(Black);
+        }
+        let (_, ply_count) = read_clocks(&mut self.trailer())?;
+        Ok(if ply_count.is_some_and(|ply| ply % 2 == 1) {
+            Black
+        } else {
+            White
+        })
+    }
+
+    /// The halfmove clock.
+    pub fn halfmoves(&self) -> Result<u32, DecompressError> {
+        read_clocks(&mut self.trailer()).map(|(halfmoves, _)| halfmoves)
+    }
+
+    /// The fullmove number.
+    pub fn fullmoves(&self) -> Result<NonZero<u32>, DecompressError> {
+        match read_clocks(&mut self.trailer())? {
+            (_, Some(ply_count)) => fullmoves(ply_count, self.turn()? == Black),
+            (_, None) => Ok(NonZero::<u32>::MIN),
+        }
+    }
+
+    /// The variant of the position.
+    pub fn variant(&self) -> Result<Variant, DecompressError> {
+        let mut trailer = self.trailer();
+        read_clocks(&mut trailer)?;
+        match trailer.first() {
+            None => Ok(Variant::Standard),
+            Some(byte) => variant_from_byte(*byte).ok_or(DecompressError::Variant(*byte)),
+        }
+    }
+
+    /// Decode the full position.
+    pub fn to_setup(&self) -> Result<Setup, DecompressError> {
+        decompress(self.bytes)
+    }
+
+    /// The piece value of the `index`th occupied square.
+    fn value(&self, index: usize) -> u8 {
+        let byte = self.bytes[8 + index / 2];
+        if index % 2 == 1 {
+            byte >> 4
+        } else {
+            byte & 0x0f
+        }
+    }
+
+    /// The occupied squares along with their piece values.
+    fn values(&self) -> impl Iterator<Item = (Square, u8)> + '_ {
+        self.occupied
+            .into_iter()
+            .enumerate()
+            .map(|(i, square)| (square, self.value(i)))
+    }
+
+    /// The bytes after the pieces.
+    fn trailer(&self) -> &'a [u8] {
+        &self.bytes[8 + self.occupied.count().div_ceil(2)..]
+    }
+}
+
+/// How [`compress_into`] and [`decompress_from`] mark the end of each
+/// position in a stream.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Framing {
+    /// The encoding is prefixed with its length in LEB128.
+    LengthPrefixed,
+    /// The halfmove clock, ply count and variant byte are always written,
+    /// even when they could be left out, as is the bitboard of promoted
+    /// pieces in crazyhouse, so that the end of the encoding follows from
+    /// its contents. Compared to [`Framing::LengthPrefixed`], this costs up
+    /// to two more bytes for positions at the start of a game, eight more
+    /// for crazyhouse positions without promoted pieces, and the same
+    /// otherwise.
+    FullTrailer,
+}
+
+/// Write a position to a stream, so that it can be followed by others.
+pub fn compress_into<W: Write>(
+    mut output: W,
+    position: &Setup,
+    variant: Variant,
+    framing: Framing,
+) -> Result<(), CompressError> {
+    match framing {
+        Framing::LengthPrefixed => {
+            let bytes = encode(position, variant, false)?;
+            leb128::write::unsigned(&mut output, bytes.as_ref().len() as u64)?;
+            output.write_all(bytes.as_ref())?;
+        }
+        Framing::FullTrailer => output.write_all(encode(position, variant, true)?.as_ref())?,
+    }
+    Ok(())
+}
+
+/// Read a position written by [`compress_into`] with the same framing,
+/// consuming only its bytes. Returns `None` if the input is at its end.
+pub fn decompress_from<R: Read>(
+    mut input: R,
+    framing: Framing,
+) -> Result<Option<(Setup, Variant)>, DecompressError> {
+    let mut first = [0];
+    if input.read(&mut first)? == 0 {
+        return Ok(None);
+    }
+    let mut input = first.chain(input);
+    let mut bytes = Vec::new();
+    match framing {
+        Framing::LengthPrefixed => {
+            let len = leb128::read::unsigned(&mut input)?;
+            if len > MAX_LEN as u64 {
+                return Err(DecompressError::Length(len));
+            }
+            bytes.resize(len as usize, 0);
+            input.read_exact(&mut bytes)?;
+        }
+        Framing::FullTrailer => {
+            bytes.resize(8, 0);
+            input.read_exact(&mut bytes)?;
+            let occupied = u64::from_be_bytes(bytes[..8].try_into().unwrap());
+            let pieces = occupied.count_ones() as usize;
+            bytes.resize(8 + pieces.div_ceil(2), 0);
+            input.read_exact(&mut bytes[8..])?;
+            read_leb128_bytes(&mut input, &mut bytes)?;
+            read_leb128_bytes(&mut input, &mut bytes)?;
+            let mut variant = [0];
+            input.read_exact(&mut variant)?;
+            bytes.push(variant[0]);
+            // This framing always writes the promoted pieces in crazyhouse.
+            let extra = match variant_from_byte(variant[0]) {
+                Some(Variant::ThreeCheck) => 1,
+                Some(Variant::Crazyhouse) => 13,
+                _ => 0,
+            };
+            let start = bytes.len();
+            bytes.resize(start + extra, 0);
+            input.read_exact(&mut bytes[start..])?;
+        }
+    }
+    decode(&bytes, false).map(Some)
+}
+
+/// Copy a LEB128 value from `input` to `bytes` without decoding it.
+fn read_leb128_bytes<R: Read>(input: &mut R, bytes: &mut Vec<u8>) -> std::io::Result<()> {
+    for _i in 0..10 {
+        let mut byte = [0];
+        input.read_exact(&mut byte)?;
+        bytes.push(byte[0]);
+        if byte[0] & 0x80 == 0 {
+            return Ok(());
+        }
+    }
+    Err(std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        "LEB128 value too long",
+    ))
+}
+
+fn encode(
+    position: &Setup,
+    variant: Variant,
+    full_trailer: bool,
+) -> Result<CompressedPosition, CompressError> {
+    check_variant_fields(position, variant)?;
+    let mut result = CompressedPosition::default();
 
     let board = &position.board;
     let occupied = board.occupied();
     result.extend(occupied.0.to_be_bytes());
 
-    let pawn_pushed_to = position
-        .ep_square
-        .map(|sq| {
-            // If it's black to play, *white* just pushed their pawn, so we
-            // offset backwards.
-            let offset = if position.turn == Black { 8 } else { -8 };
-            sq.offset(offset)
-                .map(Square::into)
-                .ok_or(CompressError::SquareOffset(sq, offset))
-        })
-        .transpose()?
-        .unwrap_or(Bitboard::EMPTY);
+    let pawn_pushed_to = pawn_pushed_to(position)?;
 
     let mut board_iter = board.clone().into_iter();
     /* We iterate over the occupancy of the board two-by-two, so that we can
@@ -147,22 +762,130 @@ pub fn compress(position: &Setup) -> Result<Vec<u8>, CompressError> {
         result.push((upper_half << 4) | lower_half);
     }
 
-    let ply = (position.fullmoves.get() - 1) * 2 + if position.turn == Black { 1 } else { 0 };
+    let ply =
+        (u64::from(position.fullmoves.get()) - 1) * 2 + if position.turn == Black { 1 } else { 0 };
     let halfmoves = position.halfmoves;
     let broken_turn = position.turn == Black && position.board.king_of(Black).is_none();
 
-    if halfmoves > 0 || ply > 1 || broken_turn {
-        leb128::write::unsigned(&mut result, halfmoves as u64)?;
+    let standard = variant == Variant::Standard && !full_trailer;
+
+    if halfmoves > 0 || ply > 1 || broken_turn || !standard {
+        result.write_leb128(halfmoves.into())?;
     }
 
-    if ply > 1 || broken_turn {
-        leb128::write::unsigned(&mut result, ply as u64)?;
+    if ply > 1 || broken_turn || !standard {
+        result.write_leb128(ply)?;
+    }
+
+    if !standard {
+        result.push(variant_byte(variant));
+    }
+    match variant {
+        Variant::ThreeCheck => {
+            let checks = position.remaining_checks.unwrap_or_default();
+            result.push((u8::from(checks.black) << 4) | u8::from(checks.white));
+        }
+        Variant::Crazyhouse => {
+            let pockets = position.pockets.unwrap_or_default();
+            for role in [Pawn, Knight, Bishop, Rook, Queen] {
+                let white = pocket_count(&pockets, Piece { color: White, role })?;
+                let black = pocket_count(&pockets, Piece { color: Black, role })?;
+                result.push((black << 4) | white);
+            }
+            if position.promoted.any() || full_trailer {
+                result.extend(position.promoted.0.to_be_bytes());
+            }
+        }
+        _ => {}
     }
 
     Ok(result)
 }
 
-fn piece_value(
+/// Reject the variant-specific fields of a setup that `variant` can't
+/// store, as upstream does.
+pub(crate) fn check_variant_fields(
+    position: &Setup,
+    variant: Variant,
+) -> Result<(), CompressError> {
+    if variant == Variant::Crazyhouse {
+        let pockets = position.pockets.unwrap_or_default();
+        for color in [White, Black] {
+            let kings = pockets.get(color).king;
+            if kings > 0 {
+                return Err(CompressError::Pocket(Piece { color, role: King }, kings));
+            }
+        }
+        let empty = position.promoted & !position.board.occupied();
+        if empty.any() {
+            return Err(CompressError::Promoted(empty));
+        }
+    } else if position.pockets.is_some() || position.promoted.any() {
+        return Err(CompressError::Pockets(variant));
+    }
+    if variant != Variant::ThreeCheck && position.remaining_checks.is_some() {
+        return Err(CompressError::RemainingChecks(variant));
+    }
+    Ok(())
+}
+
+fn pocket_count(pockets: &ByColor<ByRole<u8>>, piece: Piece) -> Result<u8, CompressError> {
+    let count = *pockets.get(piece.color).get(piece.role);
+    if count > 15 {
+        return Err(CompressError::Pocket(piece, count));
+    }
+    Ok(count)
+}
+
+fn variant_byte(variant: Variant) -> u8 {
+    match variant {
+        Variant::Standard => 0,
+        Variant::Crazyhouse => 1,
+        Variant::Chess960 => 2,
+        Variant::FromPosition => 3,
+        Variant::KingOfTheHill => 4,
+        Variant::ThreeCheck => 5,
+        Variant::Antichess => 6,
+        Variant::Atomic => 7,
+        Variant::Horde => 8,
+        Variant::RacingKings => 9,
+    }
+}
+
+fn variant_from_byte(byte: u8) -> Option<Variant> {
+    match byte {
+        0 => Some(Variant::Standard),
+        1 => Some(Variant::Crazyhouse),
+        2 => Some(Variant::Chess960),
+        3 => Some(Variant::FromPosition),
+        4 => Some(Variant::KingOfTheHill),
+        5 => Some(Variant::ThreeCheck),
+        6 => Some(Variant::Antichess),
+        7 => Some(Variant::Atomic),
+        8 => Some(Variant::Horde),
+        9 => Some(Variant::RacingKings),
+        _ => None,
+    }
+}
+
+/// The square of the pawn that just moved two squares, if any, as a
+/// bitboard.
+pub(crate) fn pawn_pushed_to(position: &Setup) -> Result<Bitboard, CompressError> {
+    Ok(position
+        .ep_square
+        .map(|sq| {
+            // If it's black to play, *white* just pushed their pawn, so we
+            // offset backwards.
+            let offset = if position.turn == Black { 8 } else { -8 };
+            sq.offset(offset)
+                .map(Square::into)
+                .ok_or(CompressError::SquareOffset(sq, offset))
+        })
+        .transpose()?
+        .unwrap_or(Bitboard::EMPTY))
+}
+
+pub(crate) fn piece_value(
     piece: Piece,
     square: Square,
     black_turn: bool,
@@ -243,8 +966,44 @@ fn piece_value(
     }
 }
 
-/// Decompress a position.
-pub fn decompress(mut bytes: &[u8]) -> Result<Setup, DecompressError> {
+/// Decompress a position, discarding the variant if there is one.
+pub fn decompress(bytes: &[u8]) -> Result<Setup, DecompressError> {
+    decompress_variant(bytes).map(|(setup, _variant)| setup)
+}
+
+/// Decompress a position along with its variant.
+pub fn decompress_variant(bytes: &[u8]) -> Result<(Setup, Variant), DecompressError> {
+    decode(bytes, false)
+}
+
+/// Decompress a position along with its variant, rejecting encodings that
+/// [`compress_variant`] would never produce: more than 32 pieces, more than
+/// one en passant pawn or black king to move, en passant pawns and castling
+/// rooks on impossible ranks, a ply count contradicting the side to move,
+/// and non-zero padding.
+///
+/// This doesn't check that the position itself is legal; see
+/// [`decompress_chess`] for that.
+pub fn decompress_strict(bytes: &[u8]) -> Result<(Setup, Variant), DecompressError> {
+    decode(bytes, true)
+}
+
+/// Decompress a standard chess (or Chess960) position in strict mode and
+/// validate it as a legal position with the given castling mode.
+pub fn decompress_chess(bytes: &[u8], mode: CastlingMode) -> Result<Chess, DecompressError> {
+    let (setup, variant) = decompress_strict(bytes)?;
+    if !matches!(
+        variant,
+        Variant::Standard | Variant::Chess960 | Variant::FromPosition
+    ) {
+        return Err(DecompressError::WrongVariant(variant));
+    }
+    setup
+        .position(mode)
+        .map_err(|e| DecompressError::Position(Box::new(e)))
+}
+
+fn decode(mut bytes: &[u8], strict: bool) -> Result<(Setup, Variant), DecompressError> {
     let occupied = Bitboard(u64::from_be_bytes(
         bytes
             .get(0..8)
@@ -253,6 +1012,9 @@ pub fn decompress(mut bytes: &[u8]) -> Result<Setup, DecompressError> {
             .unwrap(),
     ));
     let mut setup = Setup::empty();
+    if strict && occupied.count() > 32 {
+        return Err(DecompressError::TooManyPieces(occupied.count() as u32));
+    }
 
     let mut i = 8;
     let mut byte = 0;
@@ -267,43 +1029,181 @@ pub fn decompress(mut bytes: &[u8]) -> Result<Setup, DecompressError> {
         };
         read_more = !read_more;
 
-        let piece = piece_from_value(value, square);
-        setup.board.set_piece_at(square, piece);
-        if value == 12 {
-            let offset = if Bitboard::SOUTH.contains(square) {
-                -8
-            } else {
-                8
-            };
-            setup.ep_square = Some(
-                square
-                    .offset(offset)
-                    .ok_or(DecompressError::SquareOffset(square, offset))?,
-            );
-        } else if value == 13 || value == 14 {
-            setup.castling_rights |= square;
-        } else if value == 15 {
-            setup.turn = Black
+        if strict {
+            check_value(value, square, &setup)?;
         }
+        place_value(&mut setup, square, value)?;
     }
 
-    bytes = &bytes[i..];
-    if !bytes.is_empty() {
-        setup.halfmoves = leb128::read::unsigned(&mut bytes)? as u32;
+    if strict && !read_more && byte & 0xf0 != 0 {
+        return Err(DecompressError::NonZeroPadding);
     }
-    if !bytes.is_empty() {
-        let ply_count = leb128::read::unsigned(&mut bytes)? as u32;
+
+    bytes = &bytes[i..];
+    let (halfmoves, ply_count) = read_clocks(&mut bytes)?;
+    setup.halfmoves = halfmoves;
+    if let Some(ply_count) = ply_count {
         if (ply_count % 2) == 1 {
             setup.turn = Black;
+        } else if strict && setup.turn == Black {
+            return Err(DecompressError::TurnMismatch);
         }
-        let black_offset = if setup.turn == Black { 1 } else { 0 };
-        setup.fullmoves = NonZero::new((ply_count - black_offset) / 2 + 1).unwrap();
+        setup.fullmoves = fullmoves(ply_count, setup.turn == Black)?;
     }
+    if strict {
+        if let Some(ep_square) = setup.ep_square {
+            check_en_passant_turn(ep_square, setup.turn)?;
+        }
+    }
+
+    let mut variant = Variant::Standard;
+    if let Some((byte, rest)) = bytes.split_first() {
+        /* Standard chess never has a variant byte, so in strict mode it
+         * can only be trailing garbage. */
+        if strict && *byte == variant_byte(Variant::Standard) {
+            return Err(DecompressError::TrailingBytes(bytes.len()));
+        }
+        variant = variant_from_byte(*byte).ok_or(DecompressError::Variant(*byte))?;
+        bytes = rest;
+    }
+    match variant {
+        Variant::ThreeCheck => {
+            let byte = *bytes.first().ok_or(DecompressError::MissingBytes)?;
+            let checks = |n: u8| {
+                RemainingChecks::try_from(n).map_err(|_| DecompressError::RemainingChecks(n))
+            };
+            setup.remaining_checks = Some(ByColor {
+                white: checks(byte & 0x0f)?,
+                black: checks(byte >> 4)?,
+            });
+        }
+        Variant::Crazyhouse => {
+            let pockets = bytes.get(0..5).ok_or(DecompressError::MissingBytes)?;
+            let by_role = |shift: u8| ByRole {
+                pawn: (pockets[0] >> shift) & 0x0f,
+                knight: (pockets[1] >> shift) & 0x0f,
+                bishop: (pockets[2] >> shift) & 0x0f,
+                rook: (pockets[3] >> shift) & 0x0f,
+                queen: (pockets[4] >> shift) & 0x0f,
+                king: 0,
+            };
+            setup.pockets = Some(ByColor {
+                white: by_role(0),
+                black: by_role(4),
+            });
+            /* The promoted pieces are only written if there are any, so
+             * nothing after the pockets means none. */
+            if bytes.len() > 5 {
+                let promoted = bytes.get(5..13).ok_or(DecompressError::MissingBytes)?;
+                setup.promoted = Bitboard(u64::from_be_bytes(promoted.try_into().unwrap()));
+                let empty = setup.promoted & !setup.board.occupied();
+                if empty.any() {
+                    return Err(DecompressError::Promoted(empty));
+                }
+            }
+        }
+        _ => {}
+    }
+    let extra = match variant {
+        Variant::ThreeCheck => 1,
+        Variant::Crazyhouse if bytes.len() > 5 => 13,
+        Variant::Crazyhouse => 5,
+        _ => 0,
+    };
+    if bytes.len() > extra {
+        return Err(DecompressError::TrailingBytes(bytes.len() - extra));
+    }
+
+    Ok((setup, variant))
+}
+
+/// Place the piece for a piece value on a square, along with the en
+/// passant square, castling right or side to move given by special values.
+pub(crate) fn place_value(
+    setup: &mut Setup,
+    square: Square,
+    value: u8,
+) -> Result<(), DecompressError> {
+    let piece = piece_from_value(value, square);
+    setup.board.set_piece_at(square, piece);
+    if value == 12 {
+        let offset = if Bitboard::SOUTH.contains(square) {
+            -8
+        } else {
+            8
+        };
+        setup.ep_square = Some(
+            square
+                .offset(offset)
+                .ok_or(DecompressError::SquareOffset(square, offset))?,
+        );
+    } else if value == 13 || value == 14 {
+        setup.castling_rights |= square;
+    } else if value == 15 {
+        setup.turn = Black
+    }
+    Ok(())
+}
+
+/// Read the optional halfmove clock and ply count, advancing `bytes` past
+/// them.
+fn read_clocks(bytes: &mut &[u8]) -> Result<(u32, Option<u64>), DecompressError> {
+    let mut halfmoves = 0;
+    if !bytes.is_empty() {
+        let value = leb128::read::unsigned(bytes)?;
+        halfmoves = u32::try_from(value).map_err(|_| DecompressError::Halfmoves(value))?;
+    }
+    let mut ply_count = None;
+    if !bytes.is_empty() {
+        ply_count = Some(leb128::read::unsigned(bytes)?);
+    }
+    Ok((halfmoves, ply_count))
+}
 
-    Ok(setup)
+/// The fullmove number for a ply count.
+fn fullmoves(ply_count: u64, black_turn: bool) -> Result<NonZero<u32>, DecompressError> {
+    /* A black king to move with an even ply count (only rejected in strict
+     * mode) would underflow here, so saturate to move 1. */
+    let black_offset = if black_turn { 1 } else { 0 };
+    let fullmoves = ply_count.saturating_sub(black_offset) / 2 + 1;
+    u32::try_from(fullmoves)
+        .ok()
+        .and_then(NonZero::new)
+        .ok_or(DecompressError::PlyCount(ply_count))
+}
+
+/// Strict mode check that the pawn behind an en passant square was pushed by
+/// the side that isn't to move.
+fn check_en_passant_turn(ep_square: Square, turn: Color) -> Result<(), DecompressError> {
+    let (pushed_by, rank) = if ep_square.rank() == Rank::Third {
+        (White, Rank::Fourth)
+    } else {
+        (Black, Rank::Fifth)
+    };
+    if pushed_by == turn {
+        return Err(DecompressError::EnPassantTurn(Square::from_coords(
+            ep_square.file(),
+            rank,
+        )));
+    }
+    Ok(())
+}
+
+/// Strict mode checks on a piece value, given the pieces decoded so far.
+fn check_value(value: u8, square: Square, setup: &Setup) -> Result<(), DecompressError> {
+    match value {
+        12 if setup.ep_square.is_some() => Err(DecompressError::DuplicateEnPassant(square)),
+        12 if !matches!(square.rank(), Rank::Fourth | Rank::Fifth) => {
+            Err(DecompressError::EnPassantRank(square))
+        }
+        13 if square.rank() != Rank::First => Err(DecompressError::CastlingRook(square)),
+        14 if square.rank() != Rank::Eighth => Err(DecompressError::CastlingRook(square)),
+        15 if setup.turn == Black => Err(DecompressError::DuplicateTurnMarker(square)),
+        _ => Ok(()),
+    }
 }
 
-fn piece_from_value(value: u8, square: Square) -> Piece {
+pub(crate) fn piece_from_value(value: u8, square: Square) -> Piece {
     if value == 0 {
         Piece {
             color: White,
@@ -0,0 +1,214 @@
+//! Functions for compressing engine principal variations.
+//!
+//! A principal variation (PV) is a sequence of moves from an analysed
+//! position, so it is compressed with the [`crate::moves`] encoder, starting
+//! from that position. Since PVs vary in length, each one is prefixed with
+//! its number of plies in LEB128, which also makes it possible to store
+//! several PVs (as from a multi-PV search) back to back in a single blob,
+//! prefixed with the number of PVs.
+//!
+//! Moves are given and returned in UCI notation, as output by engines. The
+//! position can be given either as a [`Setup`] or as a blob produced by
+//! [`crate::position::compress`]; the castling mode (standard or Chess960)
+//! is detected from the position.
+
+use crate::{moves, position};
+use shakmaty::{CastlingMode, Chess, PositionError, Setup};
+use std::fmt::{Display, Formatter};
+
+/// Errors that can occur while compressing a principal variation.
+#[derive(Debug)]
+pub enum CompressError {
+    /// I/O error from the target data sink.
+    IO(std::io::Error),
+    /// The position is not a legal chess position.
+    Position(Box<PositionError<Chess>>),
+    /// Error while decompressing the position blob.
+    PositionBlob(position::DecompressError),
+    /// Error while compressing the moves of a PV.
+    Moves(moves::CompressError),
+}
+
+impl From<std::io::Error> for CompressError {
+    fn from(value: std::io::Error) -> Self {
+        Self::IO(value)
+    }
+}
+
+impl std::error::Error for CompressError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CompressError::IO(e) => Some(e),
+            CompressError::Position(e) => Some(e),
+            CompressError::PositionBlob(e) => Some(e),
+            CompressError::Moves(e) => Some(e),
+        }
+    }
+}
+
+impl Display for CompressError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CompressError::IO(e) => write!(f, "IO error: {e}"),
+            CompressError::Position(e) => write!(f, "Position error: {e}"),
+            CompressError::PositionBlob(e) => write!(f, "Position decompression error: {e}"),
+            CompressError::Moves(e) => write!(f, "Move compression error: {e}"),
+        }
+    }
+}
+
+/// Errors that can occur while decompressing a principal variation.
+#[derive(Debug)]
+pub enum DecompressError {
+    /// The position is not a legal chess position.
+    Position(Box<PositionError<Chess>>),
+    /// Error while decompressing the position blob.
+    PositionBlob(position::DecompressError),
+    /// Error while decompressing the moves of a PV.
+    Moves(moves::DecompressError),
+    /// Error while reading a LEB128 encoded value.
+    Leb128(leb128::read::Error),
+    /// Ply or PV count too large.
+    Count(u64),
+}
+
+impl From<leb128::read::Error> for DecompressError {
+    fn from(value: leb128::read::Error) -> Self {
+        Self::Leb128(value)
+    }
+}
+
+impl std::error::Error for DecompressError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DecompressError::Position(e) => Some(e),
+            DecompressError::PositionBlob(e) => Some(e),
+            DecompressError::Moves(e) => Some(e),
+            DecompressError::Leb128(e) => Some(e),
+            DecompressError::Count(_) => None,
+        }
+    }
+}
+
+impl Display for DecompressError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecompressError::Position(e) => write!(f, "Position error: {e}"),
+            DecompressError::PositionBlob(e) => write!(f, "Position decompression error: {e}"),
+            DecompressError::Moves(e) => write!(f, "Move decompression error: {e}"),
+            DecompressError::Leb128(e) => write!(f, "Leb128 error: {e}"),
+            DecompressError::Count(n) => write!(f, "Count {n} out of range"),
+        }
+    }
+}
+
+/// Compress a PV from a position.
+pub fn compress<S: AsRef<str>>(position: &Setup, pv: &[S]) -> Result<Vec<u8>, CompressError> {
+    let position = to_chess(position).map_err(CompressError::Position)?;
+    let mut output = Vec::new();
+    write_pv(&mut output, &position, pv)?;
+    Ok(output)
+}
+
+/// Compress a PV from a position compressed with
+/// [`crate::position::compress`].
+pub fn compress_packed<S: AsRef<str>>(position: &[u8], pv: &[S]) -> Result<Vec<u8>, CompressError> {
+    compress(
+        &position::decompress(position).map_err(CompressError::PositionBlob)?,
+        pv,
+    )
+}
+
+/// Compress several PVs from the same position.
+pub fn compress_multi<S: AsRef<str>, P: AsRef<[S]>>(
+    position: &Setup,
+    pvs: &[P],
+) -> Result<Vec<u8>, CompressError> {
+    let position = to_chess(position).map_err(CompressError::Position)?;
+    let mut output = Vec::new();
+    leb128::write::unsigned(&mut output, pvs.len() as u64)?;
+    for pv in pvs {
+        write_pv(&mut output, &position, pv.as_ref())?;
+    }
+    Ok(output)
+}
+
+/// Compress several PVs from the same position compressed with
+/// [`crate::position::compress`].
+pub fn compress_multi_packed<S: AsRef<str>, P: AsRef<[S]>>(
+    position: &[u8],
+    pvs: &[P],
+) -> Result<Vec<u8>, CompressError> {
+    compress_multi(
+        &position::decompress(position).map_err(CompressError::PositionBlob)?,
+        pvs,
+    )
+}
+
+/// Decompress a PV from a position.
+pub fn decompress(position: &Setup, mut bytes: &[u8]) -> Result<Vec<String>, DecompressError> {
+    let position = to_chess(position).map_err(DecompressError::Position)?;
+    read_pv(&mut bytes, &position)
+}
+
+/// Decompress a PV from a position compressed with
+/// [`crate::position::compress`].
+pub fn decompress_packed(position: &[u8], bytes: &[u8]) -> Result<Vec<String>, DecompressError> {
+    decompress(
+        &position::decompress(position).map_err(DecompressError::PositionBlob)?,
+        bytes,
+    )
+}
+
+/// Decompress several PVs from the same position.
+pub fn decompress_multi(
+    position: &Setup,
+    mut bytes: &[u8],
+) -> Result<Vec<Vec<String>>, DecompressError> {
+    let position = to_chess(position).map_err(DecompressError::Position)?;
+    let count = leb128::read::unsigned(&mut bytes)?;
+    /* Every PV takes at least one byte, which bounds the allocation for
+     * hostile counts. */
+    let mut pvs = Vec::with_capacity(count.min(bytes.len() as u64) as usize);
+    for _i in 0..count {
+        pvs.push(read_pv(&mut bytes, &position)?);
+    }
+    Ok(pvs)
+}
+
+/// Decompress several PVs from the same position compressed with
+/// [`crate::position::compress`].
+pub fn decompress_multi_packed(
+    position: &[u8],
+    bytes: &[u8],
+) -> Result<Vec<Vec<String>>, DecompressError> {
+    decompress_multi(
+        &position::decompress(position).map_err(DecompressError::PositionBlob)?,
+        bytes,
+    )
+}
+
+fn to_chess(setup: &Setup) -> Result<Chess, Box<PositionError<Chess>>> {
+    let mode = CastlingMode::detect(setup);
+    setup.clone().position(mode).map_err(Box::new)
+}
+
+fn write_pv<S: AsRef<str>>(
+    output: &mut Vec<u8>,
+    position: &Chess,
+    pv: &[S],
+) -> Result<(), CompressError> {
+    leb128::write::unsigned(output, pv.len() as u64)?;
+    let moves = moves::compress_uci_from(pv, position.clone()).map_err(CompressError::Moves)?;
+    output.extend(moves);
+    Ok(())
+}
+
+/// Read a single PV, advancing `bytes` past it.
+fn read_pv(bytes: &mut &[u8], position: &Chess) -> Result<Vec<String>, DecompressError> {
+    let plies = leb128::read::unsigned(bytes)?;
+    let plies = i32::try_from(plies).map_err(|_| DecompressError::Count(plies))?;
+    /* The bit reader only consumes the bytes it needs, so this leaves
+     * `bytes` at the start of the next PV. */
+    moves::decompress_to_uci_from(bytes, plies, position.clone()).map_err(DecompressError::Moves)
+}
@@ -1,20 +1,47 @@
+mod corpus;
+
 #[cfg(test)]
 mod test_move_compression {
-    use std::ops::ControlFlow;
     // These tests are copied from the Lichess compression tests:
     // https://github.com/lichess-org/compression/blob/master/src/test/scala/HuffmanPgnTest.scala
     use base64::Engine;
-    use pgn_reader::{Reader, SanPlus, Skip};
-    use shakmaty::{Chess, Move, Position};
+    use shakmaty::{
+        san::San, Chess, Color, EnPassantMode, KnownOutcome, Move, Outcome, Position, Setup,
+    };
 
-    use crate::moves::{compress, decompress};
+    use crate::moves::{
+        compress, compress_san, compress_uci, compress_unchecked, decompress, decompress_strict,
+        decompress_to_san, decompress_to_uci, decompress_unchecked, decompress_with_summary,
+        CompressError, DecompressError, NotationError,
+    };
 
-    fn parse(line: &str) -> Vec<Move> {
-        let mut reader = Reader::new(line.as_bytes());
-        let mut visitor = Visitor {
-            position: Chess::default(),
-        };
-        reader.read_game(&mut visitor).unwrap().unwrap()
+    pub(super) use super::corpus::{parse, PGNS};
+
+    /// The moves of a line of space-separated SAN moves from `position`.
+    pub(super) fn line(position: &Chess, sans: &str) -> Vec<Move> {
+        let mut position = position.clone();
+        sans.split_whitespace()
+            .map(|san| {
+                let m = san.parse::<San>().unwrap().to_move(&position).unwrap();
+                position.play_unchecked(m);
+                m
+            })
+            .collect()
+    }
+
+    /// The positions after each ply of each game in [`PGNS`], one vector per
+    /// game.
+    pub(super) fn corpus_positions() -> impl Iterator<Item = Vec<Setup>> {
+        PGNS.iter().map(|line| {
+            let mut position = Chess::default();
+            parse(line)
+                .into_iter()
+                .map(|m| {
+                    position.play_unchecked(m);
+                    position.to_setup(EnPassantMode::Legal)
+                })
+                .collect()
+        })
     }
 
     #[test]
@@ -48,122 +75,135 @@ mod test_move_compression {
         assert_eq!(parsed, decompressed);
     }
 
-    const PGNS: [&str; 114] = [
-        "d3 d5 g3 e6 Bg2 Nf6 Nf3 Be7 O-O O-O Re1 a6 e4 c5 e5 Nfd7 d4 Nb6 dxc5 Bxc5 Nc3 N8d7 a4 Be7 a5 Nc4 b3 Ncxe5 Nxe5 Nxe5 Rxe5 Bd6 Re1 Bd7 Bf4 Bc6 Bxd6 Qxd6 Na4 Rad8 Nb6 Rfe8 Ra4 Bxa4 bxa4 Qc5 Qa1 Qxa5 Qd4 Rd6 Nc4 Qb4 Nxd6 Qxd4 Nxe8 Qd2 Rb1 Qxc2 Rxb7 Qxa4 Rb8 Kf8 Nd6+ Ke7 Nf5+ Kf6 Nh4 Qd1+ Bf1 Qd4 Kg2 a5 Rb7 a4 Rxf7+ Kxf7 Nf3 Ke7 Ne5 Kd6 Nf3 Qc4 Nd4 Qc3 Nf5+ Ke5 Ne3 Kf6 Nxd5+ exd5",
-        "e4 e6 Nf3 c5 g3 a6 Bg2 Nc6 O-O d6 h3 Be7 Nc3 Qc7 d4 cxd4 Nxd4 Nxd4 Qxd4 Bf6 Qd1 e5 Nd5 Qc6 Nxf6+ Nxf6 Re1 O-O Bg5 Nd7 f4 exf4 Bxf4 Ne5 Bxe5 dxe5 a3 Be6 b4 Rad8 Qe2 Rd4 Rad1 Rfd8 Rxd4 Rxd4 c3 Rc4 Qc2 f6 Rd1 Qc7 a4 Rxb4 Rc1 Rc4 Bf1 Rc5 c4 Qb6 Qd2 Rxc4+ Kh1 Rxc1 Qxc1 Qc6 Qd1 Qxe4+ Bg2 Qd4 Qc1",
-        "e4 g6 Nf3 Bg7 d4 e6 Nc3 Ne7 Be3 O-O Be2 d6 O-O b6 Qd2 Bb7 Bh6 c5 Bxg7 Kxg7 dxc5 bxc5 Rad1 d5 exd5 exd5 Rfe1 d4 Nb5 a6 Na3 Kg8 c3 Nbc6 cxd4 cxd4 Nxd4 Nxd4 Qxd4 Qxd4 Rxd4 Nc6 Rd7 Rab8 Red1 Ne5 Re7 Nc6 Rc7 Nb4 Rdd7 Be4 Bc4 Bd5 Bxd5 Nxd5 Rxd5 Rxb2 Rc2 Rfb8 f3 Rxc2 Nxc2 Rb2 Rd8+ Kg7 Rc8 Rxa2 Nb4 Ra4 Nd5 Ra1+ Kf2 a5 Kg3 a4 Ra8 a3 Nc3 Rc1 Nb5 Rb1 Nxa3 Ra1",
-        "e4 g6 d4 Bg7 e5 e6 f4 Ne7 Nf3 d5 Nc3 O-O Be3 Nd7 Bd3 b6 Nb5 c5 Nd6 cxd4 Nxd4 Nxe5 fxe5 Bxe5 Nxc8 Rxc8 O-O Nc6 Nxc6 Rxc6 c3 Bc7 Bd4 Qd6 Qg4 Qxh2+ Kf2 e5 Rh1 Qf4+ Qxf4 exf4 Rh6 Re6 Rah1 f5 Rxh7 Bd8 Rh8+ Kf7 R1h7+ Ke8 Bb5+ Rc6 Bxc6#",
-        "e4 c5 Nf3 Nc6 Bc4 Nf6 Nc3 e5 O-O d6 a3 Nd4 Nxd4 exd4 Nd5 Be6 Nxf6+ Qxf6 Bxe6 Qxe6 c3 d3 Qa4+ Ke7 e5 d5 Re1 c4 Qb4+ Kd7 Qxb7+ Ke8 Qxa8+ Kd7 Qxa7+ Kc8 b3 Qf5 bxc4 dxc4 Qa8+ Kc7 Rb1 Bc5 Rb7+ Kc6 Qa6+ Kd5 Rf1 Re8 Rc7 Rxe5 Qc6#",
-        "b3 d5 Bb2 Nf6 g3 e5 Bg2 Nc6 d3 Be6 e3 Qd6 Nd2 O-O-O Ne2 g6 c4 dxc4 bxc4 Bg7 O-O Nd7 d4 exd4 exd4 Nb4 Ne4 Qe7 d5 Bg4 Bxg7 Rhg8 Bc3 Bxe2 Qxe2 f5 Bxb4 Qxb4 Rab1 Qa3 Nc3 Rde8 Qb2 Qa6 Qb5 Qa3 Qb4 Qa6 a4 Nb6 Qb5 Qxb5 axb5 Nxc4 d6 Nd2 Bxb7+ Kxb7 Rbe1",
-        "e4 d5 exd5 Qxd5 Nc3 Qd8 Nf3 Nf6 d4 g6 Bc4 Bg7 Bg5 O-O O-O Be6 Bxe6 fxe6 Re1 Nc6 d5 e5 dxc6",
-        "e3 e5 Nc3 Nf6 Bc4 d5 Bb3 c5 Ba4+ Bd7 Bxd7+ Qxd7 d4 exd4 exd4 cxd4 Qxd4 Qe6+ Be3 Ne4 Nf3 Nxc3 Qxc3 Nc6 Nd4 Nxd4 Qxd4 a5 a3 b5 O-O Be7 Rad1 O-O Qxd5 Qxd5 Rxd5 b4 Rd7 Rfe8 axb4 Bxb4 c3 Be7 Rfd1 a4 Ra1 h6 Rd4 Bf6 Rdxa4 Rxa4 Rxa4 g5 g3 h5 Kg2 Kg7 h4 gxh4 gxh4 Bxh4 Rxh4 Kg6 b4 Re5 Bd4 Rg5+ Kf3 f5 c4 Rg4 Rxg4+ fxg4+ Kg3 Kg5 b5 h4+ Kg2 h3+ Kh2 Kh4 Be5 Kg5 b6 Kf5 Bg3 Ke4 b7 Kf3 b8=Q Ke2 Qd8 Kf1 Qd1#",
-        "Nf3 f6 d3 e5 Nbd2 g5 e4 g4 Ng1 Bb4 Qxg4 d5 Qh5+ Kf8 a3 dxe4 axb4 exd3 Bxd3 e4 Bc4 f5 Qf7#",
-        "e4 d5 exd5 Qxd5 Nc3 Qa5 b3 c6 Bb2 Nf6 Be2 Bf5 Nf3 e6 O-O Nbd7 a3 Qc7 a4 Bd6 d3 Ng4 h3 Ngf6 Ne4 Nxe4 dxe4 Bg6 Bd3 f6 Nh4 Bf7 g3 Qb6 Kg2 g5 Nf3 h5 Nh2 Qc7 Qf3 Ne5 Qxf6 Nxd3 Qxh8+ Kd7 Qg7 Nxb2 Qxf7+ Kc8 Qe8+ Qd8 Qxe6+ Kc7 Qf7+ Be7 Qxh5 Qd2 Qf7 Qd6 Rfb1 Rf8 Qg7 Qf6 Qxf6 Bxf6 Ra2 c5 Raxb2 Bxb2 Rxb2 Rd8 c4 Rd3",
-        "e4 c6 e5 d6 exd6 exd6 d4 g6 Bd2 Bg7 Bc3 Ne7 Bc4 Be6 b3 b5 Bxe6 fxe6 d5 Nxd5 Bxg7 Rg8 Bh6 Qf6 c3 Nd7 Qf3 O-O-O Ne2 Qxf3 gxf3 Ne5 Nd4 Rde8 f4 Nd3+ Kf1 Kb7 Nd2 N5xf4 Bxf4 Nxf4 Ne4 Rgf8 Nxd6+ Kb6 Nxe8 Rxe8 Re1 c5 Rxe6+ Nxe6 Nxe6 Rxe6 Rg1 a5 Rg4 Kc6 Rg5 Kd6 Kg2 Re5 Rg4 h5 Rxg6+ Kd5 Ra6 c4 Rxa5 Kc6 b4 Rg5+ Kf1 Rg8 Ra6+",
-        "f4 d5 Nf3 Nc6 e3 e6 Bb5 Nf6 d3 Bd7 O-O a6 Ba4 b5 Bb3 Bd6 c3 O-O Bc2 h6 e4 dxe4 dxe4 Be7 e5 Nd5 Qd3 g6 f5 exf5 Bxh6 Re8 Qxd5 Be6 Qxd8 Raxd8 Nbd2 Rd5 Rae1 Bc5+ Kh1 Red8 Nb3 Bb6 Bg5 R8d7 Bf6 a5 Rd1 a4 Nc1 Be3 Nd3 Na5 Nb4 Rxd1 Rxd1 Rxd1+ Bxd1 Nc4 Nd3 Na5 Bd8 Bb6 a3 Bc4 Nb4 Kf8 Nd4 Ke8 Bf6 Bxd4 cxd4 Kd7 d5 Nb7 Bf3 Nc5 Nc6 Nd3 Na5 Nxb2 Nxc4 bxc4 e6+ fxe6 Bxb2 exd5 Bxd5 Kd6 Bxc4 g5 Bb5 c6 Bxa4 Kd5 Bc2 f4 Bc1 Ke5 Kg1 c5 Kf2 g4 g3 f3 Be3 Kd5 Bf4 c4 a4 Kc5 Be5 Kb4 a5",
-        "e4 e5 Nc3 Nf6 d3 c6 f4 exf4 Bxf4 d5 e5 d4 Nce2 Nd5 Bd2 c5 c4 Ne3 Bxe3 dxe3 Nf3 Be7 Nc3 Bg4 Be2 O-O O-O Nc6 Nd5 Nd4 Nxd4 cxd4 Bxg4 Bc5 e6 fxe6 Bxe6+ Kh8 Rxf8+ Qxf8 Qf3 Qd6 Bf5 Rf8 Qe4 g6 Bg4 a5 Bf3 Ba7 a3 Bb8 g3 Ba7 b4 h5 c5 Qd7 Qxg6 Qh7 Qxh7+ Kxh7 Bxh5 Bb8 Rf1 Rd8 Nf6+ Kh6 g4 Kg5 Kg2 axb4 axb4 Rc8 Ne4+ Kh6 Rf6+ Kg7 g5 Be5 Rf7+ Kg8 Rxb7 Rf8 Bf3 Ra8 Ng3 Ra2+ Ne2 Bf4 h4 Kf8 b5 Rd2 c6 Rxd3 b6",
-        "d4 d5 h3 e6 a3 Nf6 Nf3 b6 e3 c5 c4 cxd4 Qxd4 Nc6 Qd1 Be7 cxd5 Nxd5 Bb5 Bb7 Bxc6+ Bxc6 e4 Nf6 Qxd8+ Rxd8 Nc3 O-O e5 Bxf3 gxf3 Nd5 Nxd5 Rxd5 f4 f6 Be3 fxe5 fxe5 Rxe5 O-O-O Bf6 Kb1 Rb5 Rd2 Rd8 Rhd1 Rf8 Ka2 a5 Rd6 e5 b4 axb4 axb4 Ra8+ Kb3 Be7 Rxb6 Rxb6 Bxb6 Rb8 Ba5 Rb5 Ka4 Rb7 Re1 Bd6 Rd1 Rd7 Rc1 Kf7 Rg1 g6 h4 Ke6 Rg5 Rf7 Bb6 Rf4 h5 Kf6 Rg2 gxh5 Ba5 Rg4 Rh2 h4 f3 Rf4 Rh3 Kf5 Kb5 e4 fxe4+ Kxe4 Kc6 Be5 b5 Rf6+ Kd7 Rd6+ Ke7 Rd5 Rxh4+ Kf3 Rb4 Bd6+ Ke6 Bxb4 Kxd5 Bxa5 Kc6 h5 Kb7 h4 Ka6 Bd8 b6 Bxb6 Kxb6 h3",
-        "d4 d5 c4 c5 e3 e6 cxd5 cxd4 dxe6 dxe3 Qxd8+ Kxd8 exf7 exf2+ Kxf2 Nf6 Bg5 Be6 Nf3 Bxf7 Nc3 Be7 Be2 Nc6 Rhd1+ Kc7 Bf4+ Kc8 Bb5 Bc5+ Kf1 Nb4 a3 Nc2 Rac1 Ne3+ Bxe3 Bxe3 Rc2 a6 Re2 axb5 Rxe3 Bc4+ Kg1 Re8 Rxe8+ Nxe8 Ne5 h6 Nxc4 bxc4 Rd4 b5 Nxb5 Kb7 Nd6+ Kb6 Nxc4+ Kc5",
-        "e4 e5 Bc4 Qf6 d3 Bc5 Qf3 d6 Nc3 c6 Bg5 Qxf3 Nxf3 h6 Bh4 g5 Bg3 Nf6 h4 g4 Nd2 h5 f3 Nbd7 fxg4 Nxg4 Rf1 Ndf6 Nf3 Be6 Ng5 Bxc4 dxc4 O-O-O Bf2 Nxf2 Rxf2 Bxf2+ Kxf2 Ng4+ Kg1 Rdf8 Rf1 f6 Ne6 Kd7 Nxf8+ Rxf8 Rf5 Ke6 Rxh5 Ne3 Rh7 Nxc4 b3 Ne3 Rxb7 Nxc2 Rxa7 Rh8 g3 Rg8 Kh2 Ne3 Ra6 c5 Nb5 Rd8 h5 Ng4+ Kh3 f5 exf5+ Kxf5 Rxd6 Rxd6 Nxd6+ Kg5 a4 Nf6 a5 Nd7 Ne4+ Kxh5 a6 Kg6 a7 Nb6 Nxc5 Kf5 Nd7 e4 Nxb6 e3 a8=Q e2 Qf3+",
-        "e4 c6 Bc4 d5 exd5 cxd5 Bb3 Nf6 c3 Nc6 d3 e5 h3 Bd6 Ne2 Be6 O-O O-O f4 e4 dxe4 Nxe4 Nd4 Kh8 f5 Nxd4 cxd4 Bd7 Bxd5 Bc6 Bxc6 bxc6 Nc3 Nxc3 bxc3 c5 d5 c4 Be3 Re8 Bd4 Be5 Bxe5 Rxe5 Qd4 Qb6 Qxb6 axb6 d6 Rd5 Rf4 b5 Rd4 Rxd4 cxd4 Rd8 Rb1 g6 Rxb5 Rxd6 fxg6 fxg6 Rc5 Rxd4 a4 c3 a5 Ra4 Rxc3 Rxa5 g4 Ra2 Rc7 h5 Rc6 Kg7 gxh5 gxh5 Rc4 Kg6 h4 Kf5 Rc5+ Kg4 Rc4+ Kg3 Re4 Ra1+ Re1 Rxe1#",
-        "e4 e5 Nf3 Nc6 Bb5 Nf6 Bxc6 dxc6 d3 Bd6 O-O O-O Nbd2 b5 Qe2 a5 h3 Bd7 a4 b4 b3 c5 Nc4 Be7 Ncxe5 Be6 Bb2 Qc8 Rad1 h6 Kh2 Re8 Ng1 Nh7 f4 f6 Nef3 Bf7 f5 Bd6+ Kh1 c6 Bc1 Bc7 Qf2 Bh5 g4 Bf7 Nh4 Ng5 Bf4 Bxf4 Qxf4 Re7 Ng2 Qc7 Qxc7 Rxc7 h4 Nh7 Kh2 Re8 Kg3 Rd7 Nf4 Nf8 Nf3 Red8 g5 hxg5 hxg5 fxg5 Nxg5 Be8 Rh1 g6 Rh6 Kg7 Rdh1 Kf6 Nf3 Rg7 Kg4 Ke7 e5 Kd7 f6 Rf7 e6+ Kc7 exf7 Bxf7 Rh7 Rd7 Rxf7 Rxf7 Kg5 Kd6 Ne6",
-        "e4 c5 Nf3 Nc6 d4 cxd4 Nxd4 e5 Nb3 a6 Nc3 b5 Nd5 Bb4+ c3 Bf8 Be3 Nf6 h3 Nxe4 Bd3 f5 Bxe4 fxe4 O-O Be7 Bc5 Bxc5 Nxc5 d6 Nxe4 Be6 Ne3 O-O Qxd6 Qxd6 Nxd6 e4 a3 Ne5 Nxe4 Nd3 Rad1 Nxb2 Rd2 Nc4 Nxc4 Bxc4 Nd6 Bxf1 Kxf1 Rad8 Ne4 Rxd2 Nxd2 Rc8 c4 bxc4 Ne4 c3 Nd6 c2 Ne4 c1=Q+ Ke2 Rc2+ Kf3",
-        "d4 b6 e4 Bb7 Ba6 g6 Bxb7 Na6 Bxa8 Qxa8 Nc3 c6 Qe2 b5 Nf3 Qb7 O-O b4 Nd1 c5 dxc5 Nxc5 c3 Qxe4 Qxe4 Nxe4 Ne3 bxc3 bxc3 Nxc3 Bb2 Ne2+ Kh1 f5 Rfe1 Nf4 g3 Nh5 Ng5 Bh6 f4 Bxg5 fxg5 h6 Nd5 hxg5 Bxh8 Kf7 Bb2 e6 Nc3 Ngf6 Rad1 Ne4 Nxe4 fxe4 Rxd7+ Ke8 Rxa7 Nxg3+ hxg3 g4 Rc1 g5 Rc8#",
-        "g3 c5 Bg2 d6 Nf3 e6 O-O Nf6 d3 Be7 Nbd2 h6 b3 a6 Bb2 Nbd7 Ne4 Nxe4 dxe4 Rb8 e5 d5 Nd2 b6 c4 dxc4 Nxc4 b5 Nd6+ Kf8 Bc6 Bb7 Bxb7 Qc7 Bg2 Bg5 Ne4 Be7 f4 Nb6 Rc1 Rd8 Qc2 Nd5 Nxc5 Ne3 Nxe6+ fxe6 Qxc7 Nxf1 Rxf1",
-        "e4 b6 d3 Bb7 Nc3 d6 Be3 e5 Nf3 f6 Be2 g5 O-O h5 Qd2 g4 Ne1 Qd7 f3 Bh6 fxg4 Bxe3+ Qxe3 hxg4 Qg3 Qh7 Bxg4 Nh6 h3 Nxg4 Qxg4 Qg8",
-        "e4 e6 Nf3 c6 Nc3 d5 exd5 cxd5 d4 Nf6 Bd3 Be7 Ne5 O-O O-O Nbd7 f4 a6 Kh1 b5 a3 Bb7 Qf3 h6 Qg3 Nh7 Ng4 f5 Nxh6+ Kh8 Qg6 gxh6 Qxe6 Rf6 Qe2 Rg6 Bxf5 Rg7 Qe6 Ndf6 Re1 Bd6 Qe3",
-        "c4 c6 Nc3 e6 e4 Nf6 Nf3 Be7 d4 d5 cxd5 exd5 e5 Nfd7 Bd3 Na6 a3 Nc7 O-O Nb6 h3 Be6 Re1 Nd7 Qc2 c5",
-        "e4 c6 d3 d5 Nc3 dxe4 dxe4 Nd7 Nf3 Ngf6 Bd3 e6 O-O Bd6 Re1 Qc7 Bg5 Ng4 g3 h6 Bd2 Nde5 Nxe5 Nxe5 Bf4 Nxd3 Bxd6 Qxd6 Qxd3 Qxd3 cxd3 O-O Kf1",
-        "e4 e5 Qf3 Nf6 a4 Nc6 a5 Nxa5 Rxa5 c6 Rxe5+ Be7 Qg3 d6 Rxe7+ Qxe7 Qxg7 Rg8 Qh6 Qxe4+ Kd1 Bg4+ f3",
-        "e4 e6 Nf3 d5 e5 c5 d3 Nc6 Nbd2 Nge7 b3 Ng6 Bb2 Qc7 Qe2 Be7 O-O-O O-O Ne4 dxe4 Qxe4 b5 d4 c4 bxc4 bxc4 Bxc4 Na5 Bd3 Bb7 Nh4 Bxe4 Bxe4 Rac8",
-        "e4 e5 Nf3 Bc5 c3 f6 d4 Be7 Be3 Nc6 Nbd2 Nxd4 cxd4 exd4 Nxd4 Bb4 Be2 Qe7 O-O c5 Nf5 Qe5 Nc4 Qxe4 Nxg7+ Kd8 Qd6 Ne7 Ne6+ Ke8 Nc7+ Kf8 Bh6+ Kf7 Bh5+ Ng6 Bxg6+ Kxg6 Bf4 Qxc4 Nxa8 Qf7 Qd3+ f5 Qd6+ Qf6",
-        "d4 d5 Bg5 Nd7 g3 Ngf6 Bh3 e6 Nc3 Be7 Bxf6 Nxf6 e3 a6 Nge2 b5 O-O O-O Qb1 c5 dxc5 Bxc5 b4 Bb6 a4 Bb7 a5 Bc7 Nd1 e5 c3 e4 f4 Bc8 Bxc8 Rxc8 Nd4 Bb8 Nf2 Qd7 Ra3 Ng4 Nxg4 Qxg4 Rf2 h5 Kg2 h4 Raa2 h3+ Kh1 Ba7 Rac2 Bxd4 cxd4 Rxc2 Qxc2 Rc8 Qb2",
-        "d4 d5 f3 e6 Nh3 f5 g3 Nf6 e3 Be7 f4 c5 Qd2 cxd4 Qxd4 Nc6 Qd2 Bd7 Nc3 O-O Qe2 d4 Bd2 dxc3 Bxc3 b6 O-O-O Bc5 Kb1 b5 Bd4 Bxd4 exd4 Be8 c4 Bf7 d5 Ne7 d6 Nc6 cxb5 Nb8 d7 a6 Rc1 Nfxd7 Qd2 Nf6 Qxd8 Rxd8 bxa6 Nbd7 Bb5 Rxa6 Bxa6 Nc5 Bc4 Rc8 b4 Nce4 Bb3 Rd8 Rhd1 h6 Rxd8+ Kh7 Rdd1 Kg6 Rg1 Nd7 Rgf1 Nb6 Nf2 Nd6 Nd3 Nb5 Nc5 Nd5 Bxd5 exd5 Nd7 d4 Ne5+ Kf6 Nxf7 Kxf7 Rfd1 Nc3+ Rxc3 dxc3 Rc1 c2+ Rxc2 Kg6 Rc5 Kf6 h3 g6 Rc6+ Kf7 g4 fxg4 hxg4 g5 fxg5",
-        "e4 e5 Nf3 Nc6 Bb5 d6 Bxc6+ bxc6 d4 Nf6 dxe5 dxe5 Qxd8+ Kxd8 Nxe5 Bd6 Nxf7+ Ke8 Nxh8 Bb7 f4 Ke7 e5 Bb4+ Nc3 Bxc3+ bxc3 Ne4 O-O Nxc3 Bd2 Ne2+ Kh1 Rxh8 f5 Rf8 Rae1 Nd4 Bg5+ Ke8 f6 g6 e6 Nf5 e7 Rf7 g4 Nxe7 Rxe7+ Kf8 Rxf7+ Kxf7 Re1 c5+ Kg1 Bd5",
-        "e4 e6 c4 d5 exd5 exd5 cxd5 Nf6 b3 Nxd5 Bb2 Nf6 Nf3 Be7 Be2 O-O O-O h6 Re1 Re8 d4 Nbd7 Nc3 Nb6 Qc2 c6 Rad1 Nbd5 Ne5 Be6 Bd3",
-        "e4 e5 Nc3 d6 Bc4 f5 exf5 Bxf5 d4 e4 d5 Nf6 Nge2 Nbd7 Ng3 Bg6 h4 Ne5 Be2 Bf7 h5 g6 h6 Nfg4 Be3 Nxe3 fxe3 Qf6 Qd2 Qe7 O-O-O O-O-O Rdf1 a6 Rf2 Be8 Rhf1 Bd7 Ngxe4 Ng4 Bxg4 Bxg4 Nf6 Bxh6 Nxg4 Bg5 Qe2 Rhe8",
-        "d4 d5 Nf3 Nf6 e3 Bg4 h3 Bh5 g4 Bg6 Ne5 Nbd7 Nxg6 hxg6 Bg2 e6 a3 Nb6 g5 Nh5 e4 c6 exd5 Nxd5 Bxd5 cxd5 Nd2 Qxg5 Nf3 Qd8 Bg5 Be7 Bxe7 Qxe7 Qd3 O-O O-O-O Rac8 Ne5 Qc7 Rhg1 Nf4 Qd2 Nxh3 Rg3 Nxf2 Qxf2 Qb6 c3 Qb3 Kb1",
-        "e4 c6 Nc3 d6 d4 g6 f4 Bg7 Nf3 Nf6 Bd3 O-O O-O Bg4 Qe1 Nbd7 Be3 c5 Rd1 cxd4 Bxd4 Qa5 h3 Bxf3 Rxf3 e5 Be3 exf4 Bxf4 Ne5 Bxe5 dxe5 Nd5 Qxe1+ Rxe1 Nxd5 exd5 f5 Bc4 e4 Rb3 b6 d6+ Kh8 d7 Rad8 Bb5 a6 Bc6 b5 Rd1 Rf6 Kf2 Rxc6 a4 Rc7 axb5 Rdxd7 Rxd7 Rxd7 bxa6 Bd4+ Ke2 Ra7 Rb8+ Kg7 Rb7+ Rxb7 axb7 Ba7 c4 Kf7 b4 Ke7 c5 Kd7 b5 Kc7 c6 Bb8 Ke3 Kb6 Kd4 Kxb5 Kd5 Kb6 Ke6 Kxc6 Kf7 f4 Kg7 e3 Kxh7 e2 Kxg6 e1=Q Kg5 Qg3+ Kf5 Qxg2 h4 f3",
-        "e3 e6 b3 d5 Bb2 c5 h3 Nf6 g4 Bd6 h4 d4 h5 dxe3 dxe3 Nc6 h6 g6 g5 Rg8 Bxf6 Qb6 Nc3 e5 Nd5 Qa5+ Qd2 Qa3 Nf3 Nb4 Rd1 Be6 Bb5+ Nc6 Bxc6+ bxc6 Nc3 Be7 Ne4 Bxf6 Nxf6+ Ke7 Nxg8+ Rxg8 Qd7+ Kf8 Qd8#",
-        "Nc3 Nf6 e4 d5 e5 d4 exf6 dxc3 fxg7 cxd2+ Qxd2 Qxd2+ Bxd2 Bxg7 O-O-O O-O Ne2 Nc6 Bc3 Bg4 f3 Bf5 Bxg7 Kxg7 Ng3 Bg6 Bd3 Rad8 Be4 Bxe4 Nxe4 b6 h4 e6 c3 Ne5 Rh3 Rxd1+ Kxd1 Rd8+ Kc2 f6 h5 Kf7 Rg3 Nc4 f4 Nd6 Nxd6+ cxd6 Kd3 f5 Rg5 Rg8 Kd4 e5+ Kd5",
-        "e4 d5 exd5 Qxd5 Nc3 Qd7 Bc4 e6 Nf3 Bd6 O-O h6 d4 c6 Ne5 Bxe5 dxe5 Qxd1 Rxd1 Ne7 b4 O-O Rd3 b5 Rg3 Nf5 Rg4 bxc4 Ne4 Nd7 Rb1 Ba6 a4",
-        "d4 d5 Nf3 Nd7 c3 e6 Bf4 Ngf6 e3 c5 Bd3 c4 Bc2 b5 Nbd2 b4 Qe2 a5 e4 a4 O-O dxe4 Nxe4 Ba6 Nd6+ Bxd6 Bxd6 b3 Bb1 Nd5 Re1 Ne7",
-        "e4 c5 Nf3 d6 d4 cxd4 Nxd4 Nf6 Nc3 g6 Bc4 Bg7 f3 O-O Be3 Nc6 Qd2 Bd7 O-O-O Rc8 Bb3 a6 g4 Ne5 Rdf1 Nc4 Bxc4 Rxc4 Bh6 b5 Bxg7 Kxg7 g5 Nh5 b3 Rc5 f4 Qc7 Nd5 Rxd5 exd5 Rc8 f5 Qc3 Qxc3 Rxc3",
-        "e3 Nf6 d4 g6 c4 Bg7 Nc3 d6 Nf3 O-O Be2 Bg4 h3 Bxf3 Bxf3 c6 O-O d5 Bd2 dxc4 Be2 b5 b3 cxb3 axb3 Nd5 Nxd5 Qxd5 Qc2 c5 dxc5 Bxa1 Bxb5 Bg7 Bc4 Qc6 Rd1 Qxc5 Bxf7+ Rxf7 Qxc5 Nd7 Qc7 Nf6 Rc1 Raf8 f3 Nd5 Qc5 Rf5 Qd4 Bxd4 exd4 Nf4 Bxf4 Rxf4 Rc4 R4f7 Ra4 Rf6 Rxa7 Rb6 Ra3 Rd8 Ra4 Rxb3 Kf2 Rd3 d5 R3xd5 Re4 e5 Kg3 Kf7 f4 exf4+ Rxf4+ Ke6 Re4+ Re5 Rf4 Rf5 Rg4 Kf6 Kh2 Rd3 Rd4 Rf2 Rd6+ Rxd6 Kg3 Rdd2 Kh4 Rxg2",
-        "e4 e6 d4 d5 exd5 exd5 Nf3 Nf6 Be2 h6 Nc3 c6 Bd2 Bf5 a3 Bd6 Nh4 Bh7 Be3 Ne4 Nxe4 dxe4 g3 O-O Ng2 f5 Bc4+ Kh8 Nf4 Qb6 Bb3 a5 O-O Nd7 Ne6 Rf6 d5 c5 c4",
-        "c4 e5 e4 Nc6 d3 a6 Nf3 d6 a3 Bg4 Be2 Qf6 O-O h5 Nc3 Be6 Nd5 Bxd5 cxd5 Nd4 Nxd4 exd4 Bxh5 g6 Bf3 O-O-O Bg4+ Kb8 b4 Bh6 b5 a5 Bd2 b6 Bxh6 Rxh6 a4 Ne7 Qc2 Qe5 h3 f5 Bf3 f4 Bg4",
-        "e4 c6 Nf3 d5 exd5 cxd5 d4 Nc6 c4 dxc4 Bxc4 e6 O-O Nf6 Re1 Bb4 Nc3 O-O Bd2 a6 a3 Ba5 b4 Bb6 Be3",
-        "e4 c5 Nf3 Nc6 d4 cxd4 Nxd4 e5 Nxc6 bxc6 Nc3 Bc5 Be3 Bxe3 fxe3 Qh4+ g3 Qd8 Qd2 Nf6 O-O-O O-O h3 a5 g4 h6 Qg2 Qb6 g5 Qxe3+ Kb1 hxg5 Be2 Rb8 h4 Qxc3 b3 a4 Bc4 a3 Kc1 Qa1+ Kd2 Qxa2 hxg5 Nh7 Qh3 Re8 Qxh7+ Kf8 Qh8+ Ke7 Qxg7 Rf8 Qf6+ Ke8 g6 fxg6 Rh8 Rxh8",
-        "e4 e5 Nf3 Nf6 Bc4 Bc5 O-O O-O Nxe5 Nxe4 Re1 Qe8 d4 Bb4 Rxe4 d6 Bxf7+ Rxf7 Nxf7 Qxf7 Qe2 Qg6 Re8+ Kf7 Qe7#",
-        "e4 g6 d4 d6 Nf3 Bg7 Be3 b6 c4 Ba6 b3 e6 Nc3 Ne7 Bd3 c5 d5 Bxc3+ Nd2 Bxa1 Qxa1 exd5 Qxh8+ Kd7 Qf6 dxc4 bxc4 Ng8 Qxf7+ Kc6 Qd5+ Kd7 e5 Nc6 Qxd6+ Kc8 Qxc6+ Qc7 Qe6+ Kb8 Be4 Bb7 Qxg8+ Qc8 Qxc8+ Bxc8 Bxa8 Kxa8 O-O Be6 f4 Kb7 g4",
-        "e4 e6 d4 d5 e5 Nc6 c3 g6 Nf3 Bh6 Bxh6 Nxh6 Bd3 O-O Qd2 Nf5 h4 Qe7 h5 g5 Bxf5 exf5 Nxg5 f6 Nf3 Qg7 g3 f4 Qxf4 fxe5 Qg5 Qxg5 Nxg5 exd4 cxd4 Nxd4 Na3 Nf3+ Kf1 Nxg5 Kg2 Bh3+ Rxh3 Nxh3 Kxh3 Rxf2 b3 c5 Nb5 c4 bxc4 dxc4 Rc1 Rxa2 Rxc4 Ra5 Nd6 Rxh5+ Kg4",
-        "e4 e6 d4 d6 Nc3 Nd7 Nf3 Ne7 Bd3 Ng6 Be3 Be7 Qd2 c6 O-O-O O-O h4 b5 h5 Nh8 Kb1 a5 e5 d5 Bh6 Re8 Rh3 f5 exf6 Nxf6 Rg3 Bf8 Ne5 Nf7 Nxf7 Kxf7 Bg5 h6 Bg6+ Kg8 Bxe8 Qxe8 Bxf6 Qxh5 Qf4 Qf5 Qxh6 Kf7",
-        "d4 d6 e4 e5 dxe5 dxe5 Qxd8+ Kxd8 Nf3 Nc6 Bb5 Bd7 Nc3 f6 O-O g5 h3 h5 a3 a6 Bxc6 Bxc6 Rd1+ Ke8 Nd5 Bd6 c4 Ne7 Nxf6+ Kf7 Nd5 g4 Ng5+ Ke8 h4 Ng6 Nf6+ Ke7 Nd5+ Bxd5 cxd5 Nf4 Bxf4 exf4 Ne6 c6 Rac1 f3 g3 Rh6 Ng5 cxd5 exd5 Bc7 Re1+ Kd6 Rcd1 Bd8 Re6+ Rxe6 Nxe6",
-        "e4 d5 exd5 Qxd5 Nc3 Qe5+ Qe2 Qxe2+ Bxe2 Bf5 Nd5 Kd8 c3 c6 Ne3 e6 Nxf5 exf5 Nf3 g6 Bc4 Ke7 O-O Nf6 Re1+ Kd7 Ne5+ Kc7 Nxf7 Rg8 Ng5 Rh8 Ne6+ Kc8 d3 Nbd7 Bg5 Be7",
-        "e4 e5 Nf3 d6 d4 exd4 Qxd4 Nf6 Nc3 Nc6 Bb5 Bd7 Bxc6 Bxc6 O-O Be7 Re1 O-O Bf4 Nd7 Qd2 Bf6 Nd4 Ne5 Bxe5 Bxe5 Nxc6 bxc6 Rab1 g6 Ne2 Qh4 f4 Bg7 g3 Qf6 c3 a6 Nd4 c5 Nf3 Rab8 Re2 a5 Rc1 a4 a3 Rb3 Rc2 Rfb8 Qd5 Qe6 Qd3",
-        "e4 e5 Nc3 Qe7 Bc4 c6 d3 h6 Nge2 a5 a3 g5 Ng3 Bg7 Nf5 Qf6 O-O d6 Bd2 Bxf5 exf5 Qxf5 Ne4 d5 Nd6+ Kd7 Nxf5 Nf6 Nxg7 dxc4 dxc4 Kc8 Nf5 Nbd7 Bc3 Rd8 Nd6+ Kb8 Nxf7 Rf8 Nxe5",
-        "e4 e6 d4 c5 d5 exd5 Qxd5 d6 Bc4 Be6 Qd3 d5 exd5 Bxd5 Bxd5 Nf6 Bxb7 Qxd3 cxd3 Bd6 Bxa8 O-O Bf3 Nbd7 Nh3 Ne5 Be2 Rb8 Nc3 a6 O-O Nc6 b3 Nb4 Bb2 Nc2 Rac1 Nd4 Ne4 Nxe2+ Kh1 Nxc1 Rxc1 Nxe4 dxe4 Re8 f3 f6 Nf2 Rc8",
-        "e4 c5 b3 Nc6 Bb2 d6 Bb5 Nf6 Qe2 e5 f4 a6 Bxc6+ bxc6 fxe5 dxe5 Nf3 Bd6 O-O O-O d3 Re8 Nbd2",
-        "g3 d5 Bg2 e6 e4 Nf6 exd5 exd5 d4 Nc6 c3 Be6 h3 Be7 Bf3 Qd7 g4 O-O-O Ne2 a6 Nf4 Bd6 Be3 Rde8 Nd2 g5 Ng2 h6 h4 gxh4 Nxh4",
-        "b4 e6 Bb2 d5 a3 Nf6 e3 Be7 Bxf6 O-O Bxe7 Qxe7 d4 b6 Nd2 Bb7 c4 Nd7 cxd5 exd5 Ngf3 c5 bxc5 bxc5 Nb3 c4 Nbd2 Bc6 Nb1 Rab8 Nc3 Rb3 Qc2 Rfb8 Rb1 Qxa3 Rxb3 Rxb3 Be2 Rxc3 Qb1 Rc1+ Qxc1 Qxc1+ Bd1 Qc3+ Nd2 a5 O-O a4 Nb1 Qb2 Bxa4 Bxa4 g3 c3 Kg2 c2 Na3 Qxa3",
-        "e4 d6 Nf3 Bd7 e5 Qc8 Ng5 Nc6 Nxf7 Kxf7 exd6 cxd6 Bc4+ Ke8 Bxg8 Rxg8 O-O Nd8 Qh5+ g6 Qxh7 Rg7 Qh4 Be6 d3 Bf7 Nc3 g5 Qa4+ Nc6 d4 Be6 d5 Bd7 dxc6 Bxc6 Qd4 g4 Bf4 Rf7 Bg3 Bg7 Qd3 Bd7 Qh7",
-        "c4 e6 Nf3 Nf6 Nc3 d5 d4 Be7 Bg5 Nbd7 e3 c6 c5 h6 Bh4 a5 a3 O-O Bd3 b6 b4 axb4 axb4 Rxa1 Qxa1 bxc5 bxc5 Nh7 Bxe7 Qxe7 Qb1 Nhf6 O-O e5 dxe5 Nxe5 Nxe5 Qxe5 Ne2",
-        "d4 d5 c4 Nc6 Nc3 e5 e3 exd4 exd4 dxc4 Bxc4 Nf6 Nge2 Bb4 O-O Bg4 f3 Bh5 a3 Bd6 b4 O-O Qb3 Bg6 Bb2 Nxd4 Nxd4 Bxh2+ Kxh2 Qxd4 Ne4 Qd7 Rad1 Qf5 Ng3 Qf4 Rd4 Qh6+ Kg1 Rfe8 Rg4 Nxg4 fxg4 Qe3+ Kh2 Qxb3 Bxb3 Rad8 Nh5 Rd2 Bc1 Rd3 Bc4 Rc3 Bb5 c6 Ba4 b5 Bb2 Rce3 Bd1 a6 Nxg7 Bd3 Rf3",
-        "e4 e5 f4 d6 fxe5 dxe5 Nf3 Nc6 Bc4 Be7 O-O Nf6 Ng5 O-O Qf3 Nd4 Qh3 h6 Rxf6 Bxh3 Nxf7 Ne2+ Kh1 Rxf7 Bxf7+ Kh8 gxh3 Bxf6 d3",
-        "c4 e5 Nc3 Nf6 g3 Nc6 d3 d5 cxd5 Nxd5 Bg2 Be6 h4 Bb4 Bd2 Nxc3 Bxc6+ bxc6 bxc3 Ba3 h5 Qd5 Nf3 e4 dxe4 Qxe4 Rh4 Qf5",
-        "e4 e5 Nf3 Nc6 Bc4 Bc5 d3 Nf6 Nc3 d6 Bg5 Bg4 Nd5 Nd4 h3 Bxf3 gxf3 c6 Nxf6+ gxf6 Be3 Ne6 Qd2 Qb6 O-O-O Rg8 Rhg1 O-O-O Bxe6+ fxe6 Bxc5 Qxc5 Qe3 Qxe3+ fxe3 Kd7 f4 Ke7 f5 d5 Rxg8 Rxg8 fxe6 Kxe6 exd5+ cxd5 Rd2 Rg3 Rh2 Rxe3 Kd2 Rg3 b3 d4 c3 Kd5 c4+ Ke6 a3 f5 b4 e4 dxe4 fxe4 h4 Rxa3 Rg2 Ra2+",
-        "e4 e5 Nf3 Nc6 Bb5 Nf6 d3 a6 Ba4 b5 Bb3 Na5 c3 c6 Bc2 Bc5 b4 Bxf2+ Kxf2 Ng4+ Ke2 O-O bxa5",
-        "e4 c5 Nf3 d6 d4 cxd4 Nxd4 Nf6 Nc3 a6 Be3 e5 Nb3 Be6 f3 Be7 Qd2 Nbd7 g4 Qc7 O-O-O Rc8 g5 Nh5 Kb1 b5 Nd5 Bxd5 exd5 O-O Na5 Nb8 Bd3 f5 gxf6 Bxf6 Rhg1 Kh8 Qg2 Qxa5 Qg4 Nf4 Bxf4 exf4 Qh5 h6 Qg6 Kg8 Rde1 Be5 Qh7+ Kf7 Qf5+ Ke7 Rxg7+ Kd8 Rxe5 dxe5 Qxe5 Re8 Qd6+ Nd7 Qxd7#",
-        "e4 e5 Nf3 Nc6 Bb5 Nf6 O-O Be7 d4 exd4 Nxd4 Nxd4 Qxd4 O-O Nc3 d6 h3 h6 Bf4 b6 e5 c5 Qc4 Be6 Qa4 Nd5 Nxd5 Bxd5 Rad1 Bh4 Rxd5 Qe7 Rxd6 Qe6 Bxh6 Rfd8 Rxd8+ Rxd8 Qxh4 gxh6 Qxd8+ Kg7",
-        "e4 e5 Nf3 Nc6 Bb5 a6 Bxc6 dxc6 Nxe5 Bd6 Nf3 Nf6 Nc3 Bc5 h3 O-O O-O b5 d4 Bb4 Bg5 Be7 Qd2",
-        "Nf3 c5 g3 Nc6 Bg2 e5 Nc3 e4 Nh4 d5 b3 Be7 Bb2 Bxh4 gxh4 Qxh4 Nxd5 Qd8 Bxg7 Qxd5 Bxh8 f6 e3 Ne5 Qh5+ Kf8 d4 Bg4 Qh4 Nf3+ Bxf3 Bxf3 Bxf6 Bxh1 O-O-O Bf3 Be5",
-        "e4 e5 Bc4 c6 Nf3 d5 exd5 cxd5 Bb3 e4 d3 exf3 Qxf3 Be6 Nc3 Nf6 Bg5 Bg4 Bxf6 Bxf3 Bxd8 Kxd8 gxf3 Bc5 Bxd5 Nc6 Bxf7 Rf8 Bb3 Rxf3 O-O-O Rxf2 Ne4 Be3+ Kb1 Nd4 Nxf2 Bxf2 Rhf1 Be3 Rfe1 Bg5 Rf1 Kc7 Rde1 a6 Rf7+ Kb6 c3",
-        "f4 e6 b3 c6 Bb2 d5 Nf3 Nf6 e3 Nbd7 c4 b6 d3 Bb7 Be2 c5 O-O Qc7 Nbd2 Be7 Qc1 O-O Ne5 Nxe5 fxe5 Nd7 d4 Bg5 Rf3 Rad8 Rg3 Bh6 Nf3 dxc4 Bxc4 cxd4 Bxd4 Nc5 Ng5 Qe7 h4 Kh8 Qc2 g6",
-        "e4 c5 Nf3 Nc6 d4 cxd4 Nxd4 g6 Bc4 Bg7 Be3 e6 Nc3 Nge7 O-O O-O f4 a6 a4 Nxd4 Bxd4 Nc6 Bxg7 Kxg7 f5 exf5 exf5 Qe7 f6+ Qxf6 Rxf6 Kxf6 Nd5+ Kg7 Qf3 f5 Qf4 b5 axb5 axb5 Rxa8 Bb7 Rxf8 Kxf8 Bxb5 Kg7 Bxc6 Bxc6 Qe5+ Kf7 Qe7+ Kg8 Nf6+ Kh8 Qxh7#",
-        "e4 e5 Nf3 Nc6 Bb5 d6 Nc3 f5 d4 fxe4 Nxe4 Bg4 d5 a6 Bxc6+ bxc6 dxc6 Nf6 Nxf6+ Qxf6 Bg5 Qg6 Qd2 Qe4+ Qe3 Bxf3 gxf3 Qxc6",
-        "e4 c5 d3 d6 f4 Nf6 Nf3 g6 Nc3 Bg7 Bd2 O-O Be2 Nc6 O-O a6 h3 b5 g4 Qb6 Qc1 b4 Nd1 a5 Kh2 a4 f5 Bb7 Nf2 a3 b3 e6 Rb1 exf5 gxf5 Ne5 Nxe5 dxe5 Be3 Qc7 Ng4 Nxg4+ Bxg4 Rfd8 Bh6 f6 Bxg7 Qxg7 Rg1 g5 h4 h6 hxg5 hxg5 Kg3 Kf7 Bh5+ Ke7 Bg6 Rh8 Qe3 Rh6 Qxc5+ Kd7 Rh1 Rah8 Qb5+ Kc7 Qc4+ Kb8 Kg2 Rxh1 Rxh1 Rxh1 Kxh1 Qh6+ Kg2",
-        "e4 d5 exd5 Qxd5 Nc3 Qa5 d4 c6 Nf3 Bf5 Bd3 e6 Bxf5 Qxf5 O-O Qa5 Bd2 Nd7 Qe2 Ngf6 a3 Qc7 Rfe1 Bd6 h3 O-O Ne4 Nxe4 Qxe4 Nf6 Qh4 Rfe8 c4 Be7 Bf4 Qd8 Be5 Nd7 Qe4 Nxe5 dxe5 Qb6 b4 c5 b5 Red8 a4 a5 Red1 Qc7 Qe2 b6 Qc2 h6 Rxd8+ Rxd8 Rd1 Rxd1+ Qxd1 Qd8 Qxd8+ Bxd8 Kf1 f6 Ke2 Bc7 exf6 gxf6 g4 Kf7 Ke3 Kg6 Ke4 Kf7 Nh4 Bd6 f4 Be7 f5",
-        "d4 Nf6 c4 e6 Nf3 Bb4+ Bd2 Qe7 g3 Bxd2+ Qxd2 Nc6 Bg2 d5 O-O O-O b3 Ne4 Qc2 f5 Nbd2 Bd7 Nxe4 fxe4 Ne5 Nxd4 Qc3 Nxe2+",
-        "e4 c5 f4 Nc6 Nf3 d6 d3 Bd7 Be3 Qc7 Nbd2 O-O-O a3 e6 g3 Be7 Bg2 f6 O-O h5 b4 h4 bxc5 hxg3 hxg3 dxc5 Rb1 g5 fxg5 f5 Bf4 e5 Be3 f4 Bf2 fxg3 Bxg3 Bxg5 Nc4 Nf6 Nxg5 Rdg8 Qd2 Ng4",
-        "e4 e6 Nf3 g6 d4 Bh6 Bd3 d6 O-O Bd7 c4 Qe7 Nc3 Nc6 d5 exd5 exd5 Ne5 Re1 f6 Bxh6 Nxh6 Qd2 O-O-O Nd4 f5 Qxh6 Ng4 Rxe7 Rhe8 Qxh7 Rxe7 Qxe7 Re8 Qg5 f4 Qxf4 Nxh2 Kxh2 Rh8+ Kg1 Rh5 Bxg6 Re5 Qf8+",
-        "d4 d5 Nf3 Nf6 e3 Nc6 Be2 Bf5 a3 e6 O-O Bd6 Ne1 e5 c3 e4 f4 O-O c4 Qe7 Nc3 Be6 cxd5 Bxd5 Bg4 Bc4 Rf2 Kh8",
-        "e3 e5 d4 Nc6 d5 Nb4 e4 a6 a3 Nxd5 exd5 c6 c4 cxd5 cxd5 d6 Nc3 b6 Nge2 Bb7 g3 Nf6 Bg2 Qc8 Bg5 Be7 Rc1 h6 Bxf6 Bxf6 Ne4 Qd8 Nxf6+ Qxf6 O-O O-O f4 exf4 Nxf4 Qe7 Re1 Qc7 Rxc7 Rae8 Rxe8 Rxe8 Rxb7 Re5 Bh3 Kh7 Qc2+ g6 Rxf7+ Kg8 Qxg6+ Kh8 Qg7#",
-        "e4 c6 Nf3 Qc7 d4 Nf6 Bd3 d6 O-O Bg4 c4 Nbd7 Nbd2 e5 d5 Be7 Qc2 h5 h3 Bxf3 Nxf3 O-O b3 cxd5 exd5 Nc5 Bf5 e4 Ne1 a5 Be3 b6 Bxc5 Qxc5 Bxe4 Qd4 Bd3 Qxa1 Nf3",
-        "e4 d5 exd5 Qxd5 Nc3 Qd8 Nf3 Nf6 d4 e6 Bd3 Bb4 O-O O-O Be3 Nd5 a3 Bxc3 bxc3 Nxc3 Qd2 Nd5 Ne5 Nxe3 Qxe3 Nd7 Qh3 g6 Ng4 h5 Ne5 Nxe5 dxe5 Qg5 f4 Qh6 Qg3 Bd7 f5 exf5 Bxf5 Bxf5 Rxf5 Kh7 Rff1 Rae8 Rab1 b6 Rbc1 c5 Rcd1 Qg7 Rd7 Rxe5 h3 Rf5 Rxf5 gxf5 Qf3 Qd4+ Rxd4 cxd4 Qxf5+",
-        "e4 d5 exd5 Qxd5 Nc3 Qa5 d4 e5 Nf3 Bg4 Be2 Bb4 Bd2 Nc6 Nxe5 Bxe2 Qxe2 Nxe5 dxe5 O-O-O a3 Ne7 O-O Bxc3 Bxc3 Qb6 Rad1 Nd5 Bd4 Nf4 Qg4+ Qe6 Qxf4 Rd5 Bc3 Rhd8 Rxd5 Rxd5 h3 h6 Qg4 Qxg4 hxg4 Kd7 Re1 c5 f4 g6 Kf2 b5 Re2",
-        "e4 c5 f4 g6 Nf3 Bg7 a3 f6 c3 Qc7 e5 fxe5 fxe5 Bxe5 Nxe5 Qxe5+ Be2 d5 O-O Nf6 d4 Qd6 Bf4 Qb6 Be5 O-O dxc5 Qxc5+ Bd4 Qd6 Nd2 Nc6 Bf2 Bf5 Nf3 Ng4 Nd4 Qxh2#",
-        "e4 d5 exd5 Qxd5 Nc3 Qd8 Nf3 Nf6 Be2 e6 O-O Be7 d4 O-O Bg5 b6 h3 Ba6 Bxa6 Nxa6 a3 c5 Qd3 cxd4 Qxd4 Qxd4 Nxd4 Rac8 Rad1 h6 Bh4 Nc5 Rfe1 g5 Bg3 Nh5 Bh2 Bd8 Ndb5 a6",
-        "e4 c5 d3 Nc6 f4 e5 c3 d6 Nf3 Bg4 Be2 Bxf3 Bxf3 Nf6 O-O Be7 a4 O-O Na3 a6 Be3 Rc8 Nc4 b5 axb5 axb5 Nd2 Qd7 Qe2 h6 Ra6 b4 Rfa1 Rc7 Nc4 Rb7 fxe5 dxe5 Rxc6 Qxc6 Nxe5 Qe6 Nc4 Rbb8 e5",
-        "e4 e5 Nf3 d6 Bc4 Bg4 O-O Qf6 h3 Bxf3 Qxf3 Qxf3 gxf3 Nc6 Nc3 Nd4 Bd3 c6 Ne2 Nxf3+ Kg2 Nh4+ Kg3 Ng6 Bc4 Nh6 d4 Be7 dxe5 Bh4+ Kh2 dxe5 Be3 b5 Bb3 O-O Bxh6 gxh6 Ng3",
-        "g3 c6 Bg2 d5 e3 e6 Ne2 Nf6 d4 Be7 O-O O-O b3 h6 c4 b6 cxd5 exd5 Nbc3 Bb4 Bb2 Bxc3 Bxc3 Bf5 Nf4 Ne4 Bb4 Re8 f3 Nd6 Bh3 Bxh3 Nxh3 a5 Bxd6 Qxd6 Re1 c5 dxc5 bxc5 Nf4 d4 exd4 Rxe1+ Qxe1",
-        "d4 d5 c4 c6 Nf3 Bg4 cxd5 cxd5 Qb3 Qd7 Ne5 Qc8 Nxg4 Qxg4 Qxb7 Qd7 Qxa8 Qc7 Nc3 e6 Bd2 Bb4 Rc1 Nf6 a3 Ba5 b4 Bb6 Nxd5 Qxc1+ Bxc1 O-O Nxf6+ gxf6 Bh6 Re8 e3 e5 Bb5 Rc8 O-O exd4 exd4 Bxd4 Qf3 f5 Qxf5 Rd8 Bd3",
-        "c4 e5 g3 Nf6 Bg2 Nc6 e3 d6 Nc3 Be6 b3 Rb8 Nge2 a6 d4 exd4 exd4 Bf5 O-O Be7 Bg5 O-O Bxf6 Bxf6 d5 Ne5 Nd4 Bg6 f4 Nd7 Nde2 h6 Rc1 Bf5 Nd4 Bxd4+ Qxd4 Qf6 Qd2 Qd4+ Qxd4",
-        "Nf3 d5 g3 Nf6 Bg2 c5 O-O Nc6 d4 cxd4 Nxd4 e5 Nxc6 bxc6 c4 Bb7 cxd5 cxd5 Nc3 Bc5 Bg5 Bd4 Bxf6 gxf6 Qb3 Bxc3 Qxb7 Bd4 Bxd5 O-O Qxa8 Qxa8 Bxa8 Rxa8 Rab1 Rb8 b4 Kf8 e3 Bc3 b5 Ke7 Rfc1 Ba5 a4 Rd8 Rd1 Rc8 Rbc1 Rb8 Rc6 Bb6 Kg2 Rg8 e4 h5 h4 Rg4 f3 Rg8",
-        "e4 e6 f4 Ne7 Nf3 d5 exd5 exd5 Be2 Nf5 O-O Be7 d4 O-O Nc3 Nh4 Nxh4 Bxh4 Be3 Bf6 Qd3 c6 Bd2 Nd7 Rab1 Qc7 Nd1 c5 dxc5 Qxc5+ Be3 d4 Bf2 Qc7 Bxd4 Bxd4+ Qxd4 Nf6 Bd3 Bd7 Qf2 Bc6 Nc3 Qb6 Qxb6 axb6 a3 Nd5 Nxd5 Bxd5 c4 Bc6 f5 f6 Rbe1 Rfd8 Be4 Rd2 Bxc6 bxc6 Rf2 Rd3 Rf3 Rd2 Rf2 Rd4 Rf3 Kf7 Rb3 b5 cxb5 cxb5 Rxb5 Ra7 Ra1 Rd2 a4 Rad7 a5 Re7",
-        "d4 Nf6 c4 g6 Nc3 Bg7 e4 O-O e5 Ne8 f4 d6 Nf3 dxe5 fxe5 f6 Be2 fxe5 dxe5 Qxd1+ Bxd1 Nc6 Bf4 e6 Bg3 Bd7 Bc2 Rxf3 gxf3 Nd4 O-O-O Nxf3 Rhf1 Nxe5 Bxe5 Bxe5 Nb5 Bxb5 cxb5 Nf6 Kb1 Nd5 Bb3 Ne3",
-        "d4 b6 Nf3 Bb7 c4 Nf6 Nc3 g6 g3 Bg7 Bg2 O-O Nh4 d5 cxd5 Nxd5 Nxd5 Bxd5 e4 Bb7 Be3 e6 O-O Nd7 Rc1 Rc8 Qa4 a5 Qb5 Nf6 f3 Ra8 a4 Ba6 Qc6 Bxf1 Bxf1 Rc8 Ba6 Ra8 Bb7 Ra7 e5 Qxd4 Bxd4 Ne8 Qd7 Rxb7 Bc3 f6 Qe7 c5 Qxe6+ Kh8 exf6 Nxf6",
-        "d4 f5 Nf3 Nf6 h4 e6 Ng5 d5 e3 c6 c3 Be7 Nd2 b5 Ndf3 a5 Ne5 Ne4 Ngf7 Qc7 Nxh8 Bf6 Qh5+ Kf8 Qxh7 Bxe5 Ng6+ Kf7 Nxe5+ Kf8 Qh8+ Ke7 Qxg7+ Kd8 Qf8#",
-        "Nc3 Nf6 e4 d6 f4 g6 Nf3 Bg7 Bc4 c5 O-O O-O d3 Nc6 Kh1 a6 a3 b5 Ba2 Bb7 Nd5 Qc7 c4 Rad8 Rb1 b4 Nxc7",
-        "d4 d5 c4 e6 a3 Nf6 Nc3 Be7 Bf4 Nh5 Be5 f6 Bg3 Nxg3 fxg3 dxc4 e4 c5 d5 O-O Bxc4 a6 Nf3 b5 Be2 c4 O-O Qb6+ Kh1 e5 Nh4 Rd8 Bg4 Bb7 Nf5 Bf8 Ne3 Nd7 Be6+ Kh8 Nf5 Nc5 Qg4 Nxe6 dxe6 Qxe6 Rad1 g6 Ne3 Qxg4 Nxg4 Rxd1 Rxd1 f5 exf5 gxf5 Nxe5 Bg7 Nf7+ Kg8 Rd7 Bc6 Rc7 Be8 Nd6 Rd8 Nxf5 Rd1+ Nxd1",
-        "e4 e5 Nc3 Bb4 a3 Ba5 b4 Bb6 Na4 Nc6 Nxb6 axb6 Nf3 d6 Bc4 Bg4 h3 Bxf3 Qxf3 Qf6 Qb3 Nd4 Qa2 Qg6 O-O b5 Bd5 c6 Bb3 Nf6 d3 O-O Be3 Nf3+ Kh1 Nh4 Rg1 Qh5 c4 Ng6 Bd1 Qh4",
-        "d4 g6 e3 Nf6 Bd3 d6 h3 Bg7 f4 b6 c4 Bb7 Nf3 O-O O-O c5 d5 Qc8 Nh4 e6 e4 h6 Nc3 a6 f5 gxf5 exf5 exf5 Nxf5 Qe8",
-        "e4 e5 Nf3 d6 Bc4 Bg4 Nc3 Bxf3 Qxf3 Nf6 Nd5 Nbd7 Nxf6+ Nxf6 d3 Qd7 Bg5 Be7 Bxf6 O-O-O Bxe7 Qxe7 Bxf7 Rhf8 Qf5+ Qd7 Qxh7 Qxf7 Qh3+ Kb8 O-O-O Qf4+ Kb1",
-        "e4 e5 Nf3 Nc6 Bb5 a6 Bxc6 bxc6 Nxe5 Qe7 d4 d6 Nf3 Qxe4+ Be3 Nf6 Nc3 Qg6 O-O Bf5 Nh4 Qh5 Qxh5 Nxh5 Nxf5 g6 Ng3 Nxg3 fxg3 Bg7 Rae1 O-O",
-        "e4 a6 Nf3 b5 Be2 Bb7 e5 Nc6 O-O e6 d4 Bb4 c3 Ba5 Bg5 Nge7 a4 h6 Bxe7 Qxe7 axb5 axb5 Bxb5 O-O Nbd2 Bb6 Qc2 Ra5 Bd3 Rfa8 Rxa5 Rxa5 Nc4 Ra2 Nxb6 cxb6 Qb3 Ra8 Qxb6 d5 Qb3 g5 h3 Na5 Qc2 Ba6 Bxa6 Rxa6 Qa4 Qb7",
-        "e4 d5 exd5 Qxd5 Qf3 Qe6+ Qe3 Nc6 Qxe6 Bxe6 c3 Bf5 d4 O-O-O Be3 e5 dxe5 Nxe5 f3 b6 Nd2 Nd3+ Bxd3 Bxd3 Kf2 Nf6 Ne2 Bd6 Nb3 Rhe8 Rad1 Bc4 Nbc1 Nd5 Bd4 c5 Bxg7 Bxe2 Nxe2 Kc7 Rxd5 Bg3+ hxg3 Rxd5 Bh6 a5 Bf4+ Kc6 Rh6+ Kb7 Rxh7 Re7 g4 Red7 g3 Rd1 Be3 Ra1 a3 Ra2 Bc1 Ra1 g5 Kc6 Rh6+ Kb5 b3 Rb1 c4+ Ka6",
-        "d4 Nf6 c4 c5 d5 e6 Nc3 exd5 cxd5 d6 e4 g6 h3 Bg7 Nf3 O-O Bd3 a6 a4 Re8 O-O Nbd7 Bf4 Qc7 Rc1 Nh5 Bh2 Ne5 Nxe5 Bxe5 Bxe5 Rxe5 f4 Re8 e5 dxe5 f5 c4 Ne4 Bxf5 Rxf5 gxf5 Qxh5 fxe4 Qg5+ Kh8 Qf6+ Kg8 Bxe4 Qe7 Qf5 f6 Rc3 Kh8 d6 Qf7 Rxc4",
-        "e4 e5 Bc4 Nf6 Nc3 Bb4 Nd5 Nxd5 Bxd5 O-O a3 Ba5 b4 Bb6 Qe2 c6 Bb3 d5 exd5 cxd5 Nf3 Bg4 h3 Bh5 g4 Bg6 d3 Nc6 Be3",
-        "d4 d5 c4 Nf6 Nc3 Bf5 Nf3 Nc6 e3 e6 Be2 Bb4 O-O O-O cxd5 Nxd5 Bd2 a5 a3 Nxc3 bxc3 Bd6 c4 Qf6 c5 Be7 Rc1 Qg6 Kh1 Rfe8 Qb3 Bf6 Qxb7 Be4 Qxc7 e5 Qd7 exd4 exd4 Nxd4 Rfe1",
-        "c4 e5 Nc3 Nf6 d3 Ng4 e4 f5 exf5 Bb4 Qxg4 h5 Qe2 d5 Qxe5+ Qe7 Qxe7+ Kxe7 Bd2 Bxf5 Nxd5+ Kd6 Nxb4 b5 g3 bxc4 Bg2 cxd3 Bxa8 Re8+ Kd1 h4 gxh4 c5 Nxd3 c4 Nf4 Ke5 Nge2 c3 bxc3 Nd7 Bc6 Kd6 Bxd7 Kxd7 Rb1 a5 Rb5 a4 Rxf5 a3 Ra5 Rb8 Rg1 Rb1+ Kc2 Rb6 Rxg7+ Kd6 Rg6+ Kc7 Rxb6 Kxb6 Rxa3 Kb5 c4+ Kxc4 Rd3 Kc5 Ne6+ Kc4 Rd8 Kb5 Kc3 Ka4 Kc4 Ka3 Rb8 Kxa2 Kc3 Ka1 Kc2",
-        "c4 Nf6 Nc3 c5 e3 d6 d4 cxd4 exd4 a6 Nf3 Bg4 Be2 Bxf3 Bxf3 Nc6 O-O e6 Bg5 Be7 a3 O-O b4 e5 b5 axb5 cxb5 Nxd4 Bxb7 Rb8 Ba6 Qa5 Bd2 Nb3 Ra2 Nxd2 Qxd2 Qb6 a4 Nh5 a5 Qc5 b6 Nf4 Rb1 Qc6 f3 d5 Bb5 Qc5+ Kf1 d4 Ne4",
-        "d3 Nf6 g3 g6 Bg2 Bg7 Nf3 O-O e3 d6 h4 h5 Nh2 c5 Nc3 a6 f3 Nc6 g4 hxg4 fxg4 Qd7 Bd2 Nxg4 Nxg4 Qxg4 Qxg4 Bxg4 Bh3 Bh5 Ne2 e6 Nf4 Bf3 Rg1 e5 Ne2 Nb4 Bxb4 cxb4 Ng3 f5 Rf1 Bg4 Bxg4 fxg4 O-O-O Rxf1 Rxf1 Rf8 Rxf8+ Bxf8 Ne4 Be7 Ng5 Bxg5 hxg5 Kf7 Kd2 Ke6 Ke2 Kf5 Kf2 Kxg5 Kg3 e4 dxe4 Kf6 Kxg4 b5 Kf4 a5 e5+ dxe5+ Ke4 a4",
-        "e4 e5 Nf3 Nc6 Bc4 d6 Nc3 Nf6 d4 exd4 Nxd4 Bd7 Bf4 Be7 O-O O-O Nf3 Nh5 Be3 Bg4 h3 Bxf3 Qxf3 Ne5 Qxh5 Nxc4 Bc1 Bf6 Re1 Ne5 Bf4 Ng6 Re3 Bd4 Rg3 Nxf4 Qg4 Ng6 Rd1 Bxc3 bxc3 Qe7 Rd5 Qe6 Rg5 Qxg4 R3xg4 f6 Rh5 Ne5 Rf4 g6 Rhh4 g5",
-        "d4 d5 c4 dxc4 Nc3 Nf6 Nf3 e6 a3 Nc6 g3 Be7 Bg2 O-O O-O Bd6 e4 e5 dxe5 Nxe5 Nxe5 Bxe5 Qxd8 Rxd8 Bg5 Rd6 Nd5 Nxd5 exd5 b5 Be7 Rd7 Bb4 Rxd5 Bxd5 Ba6 Bxa8",
-        "e4 e5 Nf3 Nc6 c3 Nf6 d3 d6 Bg5 Bg4 h3 Bxf3 Qxf3 Be7 Bxf6 Bxf6 Nd2 Qe7 a3 O-O-O b4 Bg5 Nb3 b6 a4 h5 b5 Na5 Nxa5 bxa5 Be2 Qe6 O-O",
-        "d4 e6 c4 d5 Nf3 c6 Nc3 Bd6 e3 f5 Bd3 Nf6 Qc2 O-O O-O Bd7 c5 Bc7 b4 Be8 Ng5 Ne4 Nxe6 Qh4 Nxf8 Qxh2#",
-        "e4 b6 d4 Bb7 Bd3 e6 c4 Bb4+ Nc3 Bxc3+ bxc3 h6 Nf3 Nf6 Qe2 O-O O-O d6 h3 Nbd7 a4 e5 Re1 a5 Nh2 Nh7 d5 Nc5 Bc2 Bc8 f4 Qh4 Rf1 Nf6 fxe5 dxe5 Nf3 Qg3 Kh1",
-        "e4 d5 exd5 Qxd5 Nc3 Qd8 Bc4 Nf6 d3 Bg4 f3 Bf5 Be3 e6 Nge2 c6 Ng3 Bg6 Qd2 Bd6 Nce4 Nxe4 Nxe4 Bc7 Bb3 Ba5 c3 Bxe4 fxe4 O-O O-O-O b5 h4 Bb6 d4 Nd7 g4 c5 g5 cxd4 Bxd4 Bxd4 Qxd4 Nb8 Qe3 Qc7"
-    ];
+    #[test]
+    fn unchecked_round_trip() {
+        for line in PGNS {
+            let moves = parse(line);
+            let compressed = compress_unchecked(&moves).unwrap();
+            assert_eq!(compressed, compress(&moves).unwrap());
+            let decompressed =
+                decompress_unchecked(compressed.as_slice(), moves.len() as i32).unwrap();
+            assert_eq!(moves, decompressed);
+        }
+    }
+
+    #[test]
+    fn san_round_trip() {
+        for line in PGNS {
+            let sans: Vec<&str> = line.split(' ').collect();
+            let compressed = compress_san(&sans).unwrap();
+            assert_eq!(compressed, compress(&parse(line)).unwrap());
+            let decompressed = decompress_to_san(compressed.as_slice(), sans.len() as i32).unwrap();
+            assert_eq!(sans, decompressed);
+        }
+    }
+
+    #[test]
+    fn uci_round_trip() {
+        let ucis = [
+            "e2e4", "e7e5", "g1f3", "b8c6", "f1c4", "g8f6", "e1g1", "f8c5",
+        ];
+        let compressed = compress_uci(&ucis).unwrap();
+        assert_eq!(
+            compressed,
+            compress(&parse("e4 e5 Nf3 Nc6 Bc4 Nf6 O-O Bc5")).unwrap()
+        );
+        let decompressed = decompress_to_uci(compressed.as_slice(), 8).unwrap();
+        assert_eq!(ucis.as_slice(), decompressed);
+    }
+
+    #[test]
+    fn notation_errors() {
+        assert!(matches!(
+            compress_uci(&["e2e4", "zz"]),
+            Err(CompressError::Notation(1, NotationError::ParseUci(_)))
+        ));
+        assert!(matches!(
+            compress_uci(&["e2e4", "e2e4"]),
+            Err(CompressError::Notation(1, NotationError::IllegalUci(_)))
+        ));
+        assert!(matches!(
+            compress_san(&["e4", "e5", "!!"]),
+            Err(CompressError::Notation(2, NotationError::ParseSan(_)))
+        ));
+        assert!(matches!(
+            compress_san(&["e4", "e4"]),
+            Err(CompressError::Notation(1, NotationError::San(_)))
+        ));
+    }
+
+    #[test]
+    fn strict_round_trip() {
+        for line in PGNS {
+            let moves = parse(line);
+            let compressed = compress(&moves).unwrap();
+            let decompressed =
+                decompress_strict(compressed.as_slice(), moves.len() as i32).unwrap();
+            assert_eq!(moves, decompressed);
+        }
+    }
+
+    #[test]
+    fn strict_rejects_trailing_data() {
+        let bytes = [0u8; 22];
+        assert!(matches!(
+            decompress_strict(&bytes[..], 22),
+            Err(DecompressError::TrailingBytes)
+        ));
+
+        let moves = parse(PGNS[0]);
+        let mut compressed = compress(&moves).unwrap();
+        compressed.push(0);
+        assert!(matches!(
+            decompress_strict(compressed.as_slice(), moves.len() as i32),
+            Err(DecompressError::TrailingBytes)
+        ));
+    }
+
+    #[test]
+    fn strict_rejects_non_zero_padding() {
+        let mut compressed = compress(&parse("e4")).unwrap();
+        assert_eq!(compressed.len(), 1);
+        compressed[0] |= 1;
+        assert!(decompress(compressed.as_slice(), 1).is_ok());
+        assert!(matches!(
+            decompress_strict(compressed.as_slice(), 1),
+            Err(DecompressError::NonZeroPadding)
+        ));
+    }
+
+    #[test]
+    fn summary_outcome() {
+        let moves = parse(PGNS[3]);
+        let compressed = compress(&moves).unwrap();
+        let (decompressed, summary) =
+            decompress_with_summary(compressed.as_slice(), moves.len() as i32).unwrap();
+        assert_eq!(moves, decompressed);
+        assert_eq!(
+            summary.outcome,
+            Outcome::Known(KnownOutcome::Decisive {
+                winner: Color::White
+            })
+        );
+        assert!(!summary.is_threefold_repetition());
+    }
+
+    #[test]
+    fn summary_repetitions() {
+        let moves = parse("Nf3 Nf6 Ng1 Ng8 Nf3 Nf6 Ng1 Ng8");
+        let compressed = compress(&moves).unwrap();
+        let (_, summary) = decompress_with_summary(compressed.as_slice(), 8).unwrap();
+        assert_eq!(summary.outcome, Outcome::Unknown);
+        assert_eq!(summary.repetitions, 3);
+        assert_eq!(summary.max_repetitions, 3);
+        assert_eq!(summary.halfmoves, 8);
+        assert!(summary.is_threefold_repetition());
+        assert!(!summary.is_fifty_moves());
+
+        let (_, summary) = decompress_with_summary(compressed.as_slice(), 7).unwrap();
+        assert_eq!(summary.repetitions, 2);
+        assert_eq!(summary.max_repetitions, 2);
+    }
 
     const ENCODED: [&str; 114] = [
         "7qasJezzPJK15lj9CbbYheEA63S9DE37qYM/HcONsibhbJM/2xJqSwr/nVAX79Rn3x/vsAA=",
@@ -281,59 +321,1010 @@ mod test_move_compression {
         "MBXVjuQzk9ZbSKw/IzSfmL9/8WX+4bn+",
         "Hw15XVdxZr3eLz6xem8XLy8NLfv1/u0n/U0ifPQ="
     ];
+}
+
+mod test_tree_compression {
+    use super::test_move_compression::line;
+    use crate::tree::{compress, compress_from, decompress, decompress_from, Node};
+    use shakmaty::{Chess, Move, Position};
+
+    #[test]
+    fn test_linear_roundtrip() {
+        let moves = line(&Chess::default(), "e4 e5 Nf3 Nc6 Bb5 a6");
+        let roots: Vec<Node> = Node::from_line(&moves).into_iter().collect();
+        let compressed = compress(&roots).unwrap();
+        let decompressed = decompress(compressed.as_slice()).unwrap();
+        assert_eq!(roots, decompressed);
+        assert_eq!(decompressed[0].mainline(), moves);
+        let empty = compress(&[]).unwrap();
+        assert_eq!(empty.len(), 1);
+        assert!(decompress(empty.as_slice()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_variations_roundtrip() {
+        // 1. e4 e5 (1... c5 2. Nf3 (2. Nc3) 2... d6) (1... e6) 2. Nf3 *
+        let start = Chess::default();
+        let e4 = line(&start, "e4");
+        let after_e4 = start.clone().play(e4[0]).unwrap();
+        let main = Node::from_line(&line(&after_e4, "e5 Nf3")).unwrap();
+        let sicilian = line(&after_e4, "c5 Nf3 d6");
+        let mut sicilian = Node::from_line(&sicilian).unwrap();
+        let after_c5 = after_e4.clone().play(sicilian.m).unwrap();
+        sicilian
+            .children
+            .push(Node::from_line(&line(&after_c5, "Nc3")).unwrap());
+        let french = Node::from_line(&line(&after_e4, "e6")).unwrap();
+        let root = Node {
+            m: e4[0],
+            children: vec![main, sicilian, french],
+        };
+
+        let compressed = compress(std::slice::from_ref(&root)).unwrap();
+        let decompressed = decompress(compressed.as_slice()).unwrap();
+        assert_eq!(vec![root], decompressed);
+
+        let roots = vec![
+            Node::new(line(&after_e4, "c5")[0]),
+            Node::new(line(&after_e4, "e5")[0]),
+        ];
+        let compressed = compress_from(&roots, after_e4.clone()).unwrap();
+        assert_eq!(
+            roots,
+            decompress_from(compressed.as_slice(), after_e4).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_deep_line() {
+        // Knights shuffling back and forth make a line as long as we like,
+        // since the rules against repetition aren't enforced.
+        let shuffle = line(&Chess::default(), "Nf3 Nf6 Ng1 Ng8");
+        let moves: Vec<Move> = shuffle.iter().copied().cycle().take(20_000).collect();
+        let compressed = compress(&[Node::from_line(&moves).unwrap()]).unwrap();
+
+        // Far more plies than recursive calls would fit in a 1 MiB stack.
+        std::thread::Builder::new()
+            .stack_size(1024 * 1024)
+            .spawn(move || {
+                let decompressed = decompress(compressed.as_slice()).unwrap();
+                assert_eq!(decompressed[0].mainline(), moves);
+                assert_eq!(compress(&decompressed).unwrap(), compressed);
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+    }
 
-    struct Visitor {
-        position: Chess,
+    #[test]
+    fn test_deep_tree_ops() {
+        // Building, cloning, comparing and dropping a tree this deep would
+        // overflow the stack if any of them recursed.
+        let shuffle = line(&Chess::default(), "Nf3 Nf6 Ng1 Ng8");
+        let moves: Vec<Move> = shuffle.iter().copied().cycle().take(1_000_000).collect();
+        let tree = Node::from_line(&moves).unwrap();
+        let mut copy = tree.clone();
+        assert_eq!(tree, copy);
+
+        let mut node = &mut copy;
+        for _i in 0..999_990 {
+            node = &mut node.children[0];
+        }
+        node.children[0].m = shuffle[1];
+        assert_ne!(tree, copy);
+    }
+}
+
+mod test_annotations {
+    use crate::annotations::{
+        compress, decompress, format_comment, parse_comment, Annotation, CompressError,
+        DecompressError, Eval,
+    };
+
+    fn annotations() -> Vec<Annotation> {
+        vec![
+            Annotation {
+                comment: Some("Opening comment".to_string()),
+                ..Annotation::new(0)
+            },
+            Annotation {
+                nags: vec![1, 146],
+                eval: Some(Eval::Centipawns(17)),
+                clock: Some(18000),
+                ..Annotation::new(1)
+            },
+            Annotation {
+                eval: Some(Eval::Centipawns(-35)),
+                clock: Some(17950),
+                ..Annotation::new(2)
+            },
+            Annotation {
+                clock: Some(17990),
+                comment: Some("Opening comment".to_string()),
+                ..Annotation::new(3)
+            },
+            Annotation {
+                nags: vec![4, 18],
+                eval: Some(Eval::Mate(-2)),
+                clock: Some(17000),
+                ..Annotation::new(10)
+            },
+        ]
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let annotations = annotations();
+        let compressed = compress(&annotations, 10).unwrap();
+        assert_eq!(annotations, decompress(compressed.as_slice(), 10).unwrap());
+        assert!(decompress(compress(&[], 0).unwrap().as_slice(), 0)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_out_of_sync() {
+        let annotations = annotations();
+        assert!(matches!(
+            compress(&annotations, 9),
+            Err(CompressError::PlyOutOfRange(10))
+        ));
+        let mut unsorted = annotations.clone();
+        unsorted.swap(1, 2);
+        assert!(matches!(
+            compress(&unsorted, 10),
+            Err(CompressError::Unsorted(1))
+        ));
+        let compressed = compress(&annotations, 10).unwrap();
+        assert!(matches!(
+            decompress(compressed.as_slice(), 9),
+            Err(DecompressError::PlyOutOfRange(10))
+        ));
     }
 
-    impl pgn_reader::Visitor for Visitor {
-        //type Result = Vec<Move>;
-        type Tags = ();
-        type Movetext = Vec<Move>;
-        type Output = Vec<Move>;
+    #[test]
+    fn test_comments() {
+        let annotation = parse_comment("Good move [%eval -1.5] [%csl Ga4] [%clk 0:03:00.5]");
+        assert_eq!(annotation.eval, Some(Eval::Centipawns(-150)));
+        assert_eq!(annotation.clock, Some(18050));
+        assert_eq!(annotation.comment.as_deref(), Some("Good move [%csl Ga4]"));
+        assert_eq!(
+            format_comment(&annotation).unwrap(),
+            "[%eval -1.50] [%clk 0:03:00.5] Good move [%csl Ga4]"
+        );
+
+        let annotation = parse_comment(" [%eval #-3] ");
+        assert_eq!(annotation.eval, Some(Eval::Mate(-3)));
+        assert_eq!(annotation.comment, None);
+        assert_eq!(
+            parse_comment(&format_comment(&annotation).unwrap()),
+            annotation
+        );
+        assert_eq!(format_comment(&Annotation::new(3)), None);
 
-        fn begin_tags(&mut self) -> ControlFlow<Self::Output, Self::Tags> {
-            ControlFlow::Continue(())
+        // Evaluations that can't be stored as they are stay in the text.
+        for eval in [
+            "-1.5,23",
+            "nan",
+            "inf",
+            "-inf",
+            "1e3",
+            "99999999999",
+            "#",
+            ".",
+        ] {
+            let comment = format!("[%eval {eval}]");
+            let annotation = parse_comment(&comment);
+            assert_eq!(annotation.eval, None, "{eval}");
+            assert_eq!(annotation.comment.as_deref(), Some(comment.as_str()));
         }
+        assert_eq!(
+            parse_comment("[%eval +.5]").eval,
+            Some(Eval::Centipawns(50))
+        );
+    }
+}
+
+mod test_eval_compression {
+    use crate::eval::{compress, decompress, DecompressError, Eval};
+
+    #[test]
+    fn test_roundtrip() {
+        let evals = vec![
+            Some(Eval::Centipawns(17)),
+            Some(Eval::Centipawns(25)),
+            None,
+            Some(Eval::Centipawns(-340)),
+            Some(Eval::Mate(4)),
+            Some(Eval::Mate(3)),
+            Some(Eval::Centipawns(i32::MIN)),
+            Some(Eval::Centipawns(i32::MAX)),
+            Some(Eval::Mate(-1)),
+            None,
+        ];
+        let compressed = compress(&evals).unwrap();
+        assert_eq!(
+            evals,
+            decompress(compressed.as_slice(), evals.len()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_small_swings() {
+        let evals: Vec<_> = (0..80)
+            .map(|i| Some(Eval::Centipawns(20 + (i % 5) * 3 - 6)))
+            .collect();
+        let compressed = compress(&evals).unwrap();
+        // 64 differences of 3 centipawns at 6 bits, and the initial 14 and
+        // 15 differences of -12 at 8 bits.
+        assert_eq!(compressed.len(), 64);
+        assert_eq!(
+            evals,
+            decompress(compressed.as_slice(), evals.len()).unwrap()
+        );
+        assert_eq!(Eval::Mate(-3).to_string(), "#-3");
+        assert_eq!(Eval::Centipawns(-5).to_string(), "-0.05");
+    }
+
+    #[test]
+    fn test_malformed() {
+        let compressed = compress(&[Some(Eval::Mate(2))]).unwrap();
+        assert!(matches!(
+            decompress(compressed.as_slice(), 3),
+            Err(DecompressError::MissingBytes)
+        ));
+        // A centipawn entry whose code has more leading zeros than any
+        // 32-bit difference.
+        assert!(matches!(
+            decompress([0u8; 8].as_slice(), 1),
+            Err(DecompressError::OutOfRange)
+        ));
+    }
+}
 
-        fn begin_movetext(
-            &mut self,
-            _tags: Self::Tags,
-        ) -> ControlFlow<Self::Output, Self::Movetext> {
-            self.position = Chess::default();
-            ControlFlow::Continue(Vec::new())
+mod test_pv_compression {
+    use crate::position;
+    use crate::pv::{
+        compress, compress_multi, compress_multi_packed, decompress, decompress_multi,
+        decompress_packed, CompressError,
+    };
+    use shakmaty::fen::Fen;
+
+    #[test]
+    fn test_roundtrip() {
+        let setup =
+            Fen::from_ascii(b"r3k2r/pppq1ppp/2n1bn2/3p4/3P4/2N1BN2/PPPQ1PPP/R3K2R w KQkq - 4 9")
+                .unwrap()
+                .into_setup();
+        let pv = ["e1g1", "e8c8", "f3e5", "c6e5", "d4e5"];
+        let compressed = compress(&setup, &pv).unwrap();
+        assert_eq!(compressed[0], 5);
+        assert_eq!(pv.as_slice(), decompress(&setup, &compressed).unwrap());
+
+        let packed = position::compress(&setup).unwrap();
+        assert_eq!(
+            pv.as_slice(),
+            decompress_packed(&packed, &compressed).unwrap()
+        );
+
+        let pvs = vec![vec!["e1g1", "e8c8"], vec![], vec!["a2a3"]];
+        let compressed = compress_multi(&setup, &pvs).unwrap();
+        assert_eq!(compressed, compress_multi_packed(&packed, &pvs).unwrap());
+        assert_eq!(pvs, decompress_multi(&setup, &compressed).unwrap());
+    }
+
+    #[test]
+    fn test_errors() {
+        let setup = Fen::from_ascii(b"8/8/8/8/8/8/8/8 w - - 0 1")
+            .unwrap()
+            .into_setup();
+        assert!(matches!(
+            compress(&setup, &["e2e4"]),
+            Err(CompressError::Position(_))
+        ));
+    }
+}
+
+mod test_header_compression {
+    use crate::header::{
+        compress, decompress, CompressError, Date, DecompressError, Header, Termination,
+        TimeControl, Variant,
+    };
+    use shakmaty::{Color, KnownOutcome, Outcome};
+
+    #[test]
+    fn test_roundtrip() {
+        let tags = [
+            ("Event", "Rated Blitz game"),
+            ("Site", "https://lichess.org/abcdefgh"),
+            ("Date", "2024.03.??"),
+            ("White", "alice"),
+            ("Black", "bob"),
+            ("Result", "0-1"),
+            ("WhiteElo", "1850"),
+            ("BlackElo", "1903"),
+            ("WhiteRatingDiff", "-6"),
+            ("BlackRatingDiff", "+5"),
+            ("Variant", "Chess960"),
+            ("TimeControl", "180+2"),
+            ("Termination", "Time forfeit"),
+        ];
+        let header = Header::from_tags(&tags);
+        assert_eq!(
+            header.result,
+            Outcome::Known(KnownOutcome::Decisive {
+                winner: Color::Black
+            })
+        );
+        assert_eq!(header.termination, Termination::TimeForfeit);
+        assert_eq!(header.variant, Variant::Chess960);
+        assert_eq!(
+            header.date,
+            Some(Date {
+                year: Some(2024),
+                month: Some(3),
+                day: None
+            })
+        );
+
+        let compressed = compress(&header).unwrap();
+        // 3 bytes of flags, result and variant, 1 time control, 2+2 ratings,
+        // 1+1 diffs, 3 date and 6+4 player bytes.
+        assert_eq!(compressed.len(), 23);
+        let decompressed = decompress(&compressed).unwrap();
+        assert_eq!(header, decompressed);
+
+        let roundtrip: Vec<_> = decompressed.to_tags();
+        for tag in [
+            "Date",
+            "White",
+            "Black",
+            "Result",
+            "WhiteElo",
+            "BlackElo",
+            "WhiteRatingDiff",
+            "BlackRatingDiff",
+            "Variant",
+            "TimeControl",
+            "Termination",
+        ] {
+            let original = tags.iter().find(|(name, _)| *name == tag).unwrap().1;
+            let value = &roundtrip.iter().find(|(name, _)| *name == tag).unwrap().1;
+            assert_eq!(original, value);
         }
+    }
+
+    #[test]
+    fn test_minimal() {
+        let header = Header::default();
+        let compressed = compress(&header).unwrap();
+        assert_eq!(compressed, [0, 0, 1]);
+        assert_eq!(header, decompress(&compressed).unwrap());
 
-        fn end_game(&mut self, movetext: Self::Movetext) -> Self::Output {
-            movetext
+        let header = Header {
+            time_control: Some(TimeControl {
+                initial: 86400,
+                increment: 0,
+            }),
+            ..Header::default()
+        };
+        assert_eq!(header, decompress(&compress(&header).unwrap()).unwrap());
+        assert!(matches!(
+            decompress(&[0, 0]),
+            Err(DecompressError::MissingBytes)
+        ));
+        assert!(matches!(
+            decompress(&[0, 0, 11]),
+            Err(DecompressError::Variant(11))
+        ));
+        assert!(matches!(
+            decompress(&[0, 0, 1, 0]),
+            Err(DecompressError::TrailingBytes(1))
+        ));
+    }
+
+    #[test]
+    fn test_date_range() {
+        for (month, day) in [(Some(16), None), (Some(13), Some(1)), (None, Some(32))] {
+            let header = Header {
+                date: Some(Date {
+                    year: Some(2024),
+                    month,
+                    day,
+                }),
+                ..Header::default()
+            };
+            assert!(matches!(compress(&header), Err(CompressError::Date(_))));
         }
+        let header = Header {
+            date: Some(Date {
+                year: Some(2024),
+                month: Some(12),
+                day: Some(31),
+            }),
+            ..Header::default()
+        };
+        assert_eq!(header, decompress(&compress(&header).unwrap()).unwrap());
 
-        fn begin_variation(
-            &mut self,
-            _movetext: &mut Self::Movetext,
-        ) -> ControlFlow<Self::Output, Skip> {
-            ControlFlow::Continue(Skip(true))
+        // Month 13 of year 0 and no day, which compress never writes.
+        let mut bytes = vec![1 << 5, 0, 1];
+        leb128::write::unsigned(&mut bytes, 13 * 32).unwrap();
+        assert!(matches!(
+            decompress(&bytes),
+            Err(DecompressError::OutOfRange(13))
+        ));
+    }
+}
+
+mod test_plyset_compression {
+    use crate::moves::{compress_san, decompress};
+    use crate::plyset::{
+        compress, decompress as decompress_set, from_flags, select, to_flags, CompressError,
+        DecompressError,
+    };
+
+    #[test]
+    fn test_sparse() {
+        let set = [31, 33, 80];
+        let compressed = compress(&set, 120).unwrap();
+        assert!(compressed.len() < 120 / 8);
+        assert_eq!(
+            set.as_slice(),
+            decompress_set(compressed.as_slice(), 120).unwrap()
+        );
+        assert!(matches!(
+            decompress_set(compressed.as_slice(), 80),
+            Err(DecompressError::PlyOutOfRange(80))
+        ));
+        assert!(compress(&[], 0).unwrap().len() == 1);
+    }
+
+    #[test]
+    fn test_dense() {
+        let set: Vec<u32> = (0..100).filter(|ply| ply % 3 != 0).collect();
+        let compressed = compress(&set, 100).unwrap();
+        assert_eq!(compressed.len(), 101usize.div_ceil(8));
+        assert_eq!(set, decompress_set(compressed.as_slice(), 100).unwrap());
+        assert_eq!(set, from_flags(&to_flags(&set, 100)));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(matches!(
+            compress(&[3, 10], 10),
+            Err(CompressError::PlyOutOfRange(10))
+        ));
+        assert!(matches!(
+            compress(&[3, 3], 10),
+            Err(CompressError::Unsorted(3))
+        ));
+    }
+
+    #[test]
+    fn test_select() {
+        let sans = ["e4", "e5", "Nf3", "Nc6", "Bb5"];
+        let moves = decompress(compress_san(&sans).unwrap().as_slice(), 5).unwrap();
+        let offers = [1, 4];
+        let selected = select(&moves, &offers);
+        assert_eq!(selected, vec![(1, &moves[1]), (4, &moves[4])]);
+    }
+}
+
+mod test_archive {
+    use super::test_move_compression::line;
+    use crate::archive::{ArchiveReader, ArchiveWriter, Game, ReadError};
+    use shakmaty::{fen::Fen, CastlingMode, Chess};
+    use std::io::Cursor;
+
+    fn archive() -> (Vec<Game>, Vec<u8>) {
+        let start: Chess = Fen::from_ascii(b"4k3/8/8/8/8/8/4P3/4K3 w - - 0 1")
+            .unwrap()
+            .into_position(CastlingMode::Standard)
+            .unwrap();
+        let games = vec![
+            Game::new(&line(&Chess::default(), "e4 e5 Nf3 Nc6")).unwrap(),
+            Game::new(&[]).unwrap(),
+            Game::from_position(&line(&start, "e4 Kd7 Kd2"), &start).unwrap(),
+        ];
+        let mut writer = ArchiveWriter::new(Vec::new()).unwrap();
+        for game in &games {
+            writer.add(game).unwrap();
         }
+        assert_eq!(writer.len(), 3);
+        (games, writer.finish().unwrap())
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let (games, bytes) = archive();
+        let mut reader = ArchiveReader::new(Cursor::new(bytes)).unwrap();
+        assert_eq!(reader.len(), 3);
+        assert_eq!(games[2], reader.game(2).unwrap());
+        assert_eq!(games[0], reader.game(0).unwrap());
+        let read: Vec<Game> = reader.games().map(Result::unwrap).collect();
+        assert_eq!(games, read);
+        assert_eq!(read[2].decode().unwrap().len(), 3);
+        assert!(matches!(reader.game(3), Err(ReadError::Index(3))));
+    }
+
+    #[test]
+    fn test_empty() {
+        let bytes = ArchiveWriter::new(Vec::new()).unwrap().finish().unwrap();
+        assert_eq!(bytes.len(), 17);
+        let mut reader = ArchiveReader::new(Cursor::new(bytes)).unwrap();
+        assert!(reader.is_empty());
+        assert!(reader.games().next().is_none());
+    }
+
+    #[test]
+    fn test_corrupt() {
+        let (_, mut bytes) = archive();
+        bytes[4] = 2;
+        assert!(matches!(
+            ArchiveReader::new(Cursor::new(&bytes)),
+            Err(ReadError::Version(2))
+        ));
+        bytes[4] = 1;
+        let len = bytes.len();
+        bytes[len - 12] = 200;
+        assert!(matches!(
+            ArchiveReader::new(Cursor::new(&bytes)),
+            Err(ReadError::OutOfRange(200))
+        ));
+        bytes.truncate(len - 1);
+        assert!(matches!(
+            ArchiveReader::new(Cursor::new(&bytes)),
+            Err(ReadError::Magic)
+        ));
+    }
+}
 
-        fn san(
-            &mut self,
-            movetext: &mut Self::Movetext,
-            san: SanPlus,
-        ) -> ControlFlow<Self::Output> {
-            let m = san
-                .san
-                .to_move(&self.position)
-                .expect("Bad algebraic notation");
-            self.position.play_unchecked(m);
-            movetext.push(m);
-            ControlFlow::Continue(())
+mod test_dense_archive {
+    use super::test_move_compression::line;
+    use crate::archive::ReadError;
+    use crate::dense::{DenseReader, DenseWriter};
+    use crate::moves::compress;
+    use shakmaty::{Chess, Move};
+    use std::io::Cursor;
+
+    #[test]
+    fn test_roundtrip() {
+        let games = vec![
+            line(&Chess::default(), "e4 e5 Nf3 Nc6 Bb5"),
+            line(&Chess::default(), ""),
+            line(&Chess::default(), "d4 Nf6 c4"),
+            line(&Chess::default(), "f3 e5 g4 Qh4#"),
+        ];
+        let mut writer = DenseWriter::new(Vec::new()).unwrap();
+        for game in &games {
+            writer.add(game).unwrap();
         }
+        let bytes = writer.finish().unwrap();
+
+        let mut reader = DenseReader::new(Cursor::new(&bytes)).unwrap();
+        assert_eq!(reader.len(), 4);
+        assert_eq!(reader.plies(3).unwrap(), 4);
+        assert_eq!(games[2], reader.game(2).unwrap());
+        assert_eq!(games[0], reader.game(0).unwrap());
+        let read: Vec<Vec<Move>> = reader.games().map(Result::unwrap).collect();
+        assert_eq!(games, read);
+        assert!(matches!(reader.game(4), Err(ReadError::Index(4))));
+
+        /* The packed stream is no longer than the games compressed one by
+         * one, and usually shorter. */
+        let separate: usize = games.iter().map(|g| compress(g).unwrap().len()).sum();
+        let index = 4 * 2 + 20;
+        assert!(bytes.len() - 5 - index < separate);
+    }
+
+    #[test]
+    fn test_illegal_move() {
+        let games = [
+            line(&Chess::default(), "e4 e5"),
+            line(&Chess::default(), "d4"),
+        ];
+        let mut illegal = line(&Chess::default(), "Nf3 d5");
+        illegal.push(games[0][1]);
+
+        let mut bytes = Vec::new();
+        let mut writer = DenseWriter::new(&mut bytes).unwrap();
+        writer.add(&games[0]).unwrap();
+        assert!(writer.add(&illegal).is_err());
+        writer.add(&games[1]).unwrap();
+        writer.finish().unwrap();
+
+        let mut reader = DenseReader::new(Cursor::new(&bytes)).unwrap();
+        let read: Vec<Vec<Move>> = reader.games().map(Result::unwrap).collect();
+        assert_eq!(games.to_vec(), read);
+    }
+
+    #[test]
+    fn test_corrupt() {
+        let mut bytes = Vec::new();
+        let mut writer = DenseWriter::new(&mut bytes).unwrap();
+        writer.add(&line(&Chess::default(), "e4 e5")).unwrap();
+        writer.finish().unwrap();
+
+        let len = bytes.len();
+        let mut corrupt = bytes.clone();
+        corrupt[len - 12] = 3;
+        assert!(matches!(
+            DenseReader::new(Cursor::new(&corrupt)),
+            Err(ReadError::OutOfRange(3))
+        ));
+        let mut corrupt = bytes.clone();
+        corrupt[len - 20] = 0;
+        assert!(matches!(
+            DenseReader::new(Cursor::new(&corrupt)),
+            Err(ReadError::OutOfRange(0))
+        ));
     }
 }
 
-mod test_position_compression {
+mod test_frame {
+    use super::test_move_compression::line;
+    use crate::frame::{
+        compress_moves, compress_position, crc32, decompress_moves, decompress_position, frame,
+        read_frame, verify, DecompressError, Kind,
+    };
+    use shakmaty::{fen::Fen, Chess};
+
+    #[test]
+    fn test_crc32() {
+        // The standard check values of CRC-32 (IEEE), as computed by zlib.
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(
+            crc32(b"The quick brown fox jumps over the lazy dog"),
+            0x414f_a339
+        );
+    }
+
+    #[test]
+    fn test_stable_format() {
+        let framed = frame(Kind::Moves, b"123456789");
+        assert_eq!(hex::encode(&framed), "010009313233343536373839428c9831");
+        let mut input = framed.as_slice();
+        let read = read_frame(&mut input).unwrap();
+        assert_eq!(read.kind, Kind::Moves);
+        assert_eq!(read.payload, b"123456789");
+        assert!(input.is_empty());
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let moves = line(&Chess::default(), "e4 e5 Nf3 Nc6 Bb5 a6");
+        let framed = compress_moves(&moves).unwrap();
+        assert_eq!(moves, decompress_moves(&framed, 6).unwrap());
+
+        let setup = Fen::from_ascii(b"4k3/8/8/8/8/8/4P3/4K3 w - - 0 1")
+            .unwrap()
+            .into_setup();
+        let framed_position = compress_position(&setup).unwrap();
+        assert_eq!(setup, decompress_position(&framed_position).unwrap());
+        assert!(matches!(
+            decompress_position(&framed),
+            Err(DecompressError::WrongKind(Kind::Moves))
+        ));
+    }
+
+    #[test]
+    fn test_corruption() {
+        let moves = line(&Chess::default(), "e4 e5 Nf3 Nc6 Bb5 a6");
+        let good = compress_moves(&moves).unwrap();
+        let mut bad = good.clone();
+        bad[4] ^= 0x10;
+        assert!(matches!(
+            decompress_moves(&bad, 6),
+            Err(DecompressError::Checksum { .. })
+        ));
+        assert!(matches!(
+            decompress_moves(&good[..good.len() - 1], 6),
+            Err(DecompressError::MissingBytes)
+        ));
+
+        let mut archive = [good.clone(), bad, good.clone(), good.clone()].concat();
+        let failures = verify(&archive);
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].0, 1);
+
+        archive.truncate(archive.len() - 2);
+        let failures = verify(&archive);
+        assert_eq!(
+            failures.iter().map(|(i, _)| *i).collect::<Vec<_>>(),
+            vec![1, 3]
+        );
+        assert!(verify(&[good.clone(), good].concat()).is_empty());
+    }
+}
+
+mod test_position_keys {
+    use crate::key::{key, normalize, KeyOptions};
     use crate::position::{compress, decompress};
-    use shakmaty::fen::Fen;
+    use shakmaty::{fen::Fen, CastlingMode, Chess, EnPassantMode, Position};
+
+    fn chess(fen: &str) -> Chess {
+        Fen::from_ascii(fen.as_bytes())
+            .unwrap()
+            .into_position(CastlingMode::Standard)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_clocks() {
+        let a = chess("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3");
+        let b = chess("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 6 17");
+        assert_ne!(
+            compress(&a.to_setup(EnPassantMode::Legal)).unwrap(),
+            compress(&b.to_setup(EnPassantMode::Legal)).unwrap()
+        );
+        let options = KeyOptions::default();
+        assert_eq!(key(&a, options).unwrap(), key(&b, options).unwrap());
+
+        let options = KeyOptions {
+            halfmoves: true,
+            ..KeyOptions::default()
+        };
+        assert_ne!(key(&a, options).unwrap(), key(&b, options).unwrap());
+        let setup = decompress(key(&b, options).unwrap().as_ref()).unwrap();
+        assert_eq!((setup.halfmoves, setup.fullmoves.get()), (6, 1));
+    }
+
+    #[test]
+    fn test_en_passant() {
+        // No black pawn can capture on e3.
+        let a = chess("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1");
+        let b = chess("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1");
+        let options = KeyOptions::default();
+        assert_eq!(key(&a, options).unwrap(), key(&b, options).unwrap());
+
+        let c = chess("rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 3");
+        assert_eq!(
+            normalize(&c, options).ep_square,
+            c.ep_square(EnPassantMode::Legal)
+        );
+        assert!(normalize(&c, options).ep_square.is_some());
+    }
+
+    #[test]
+    fn test_white_to_move() {
+        let white = chess("rnbqkbnr/pppp1ppp/8/4p3/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 2");
+        let black = chess("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1");
+        let options = KeyOptions::default();
+        assert_ne!(key(&white, options).unwrap(), key(&black, options).unwrap());
+
+        let options = KeyOptions {
+            white_to_move: true,
+            ..KeyOptions::default()
+        };
+        assert_eq!(key(&white, options).unwrap(), key(&black, options).unwrap());
+        assert_eq!(normalize(&black, options).turn, shakmaty::Color::White);
+    }
+}
+
+mod test_compact_positions {
+    use super::test_move_compression::corpus_positions;
+    use super::test_position_compression::setup;
+    use crate::compact::{compress, decompress, DecompressError};
+    use crate::position::{compress_as, decompress_as, CompressError, Format, Variant};
+    use shakmaty::{Bitboard, Setup, Square};
+
+    #[test]
+    fn round_trip() {
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+            "r3k2r/8/8/8/8/8/8/R3K2R b Qk - 17 42",
+            "8/8/8/8/8/8/8/8 b - - 0 1",
+            "8/8/8/8/8/8/8/8 w - - 4294967295 4294967295",
+            "4k3/8/8/8/8/8/8/4K3 b - - 0 1",
+        ] {
+            let setup = setup(fen);
+            for format in [Format::BinaryFen, Format::Compact] {
+                let bytes = compress_as(&setup, format).unwrap();
+                assert_eq!(decompress_as(&bytes, format).unwrap(), setup, "{fen}");
+            }
+        }
+    }
+
+    #[test]
+    fn start_position() {
+        let setup = Setup::default();
+        // 32 empty squares at one bit, 16 pawns at three, 8 minor pieces at
+        // five, 4 castling rooks at six, two queens and two kings at seven,
+        // plus the clock flag: 173 bits, against 192 for BinaryFen.
+        assert_eq!(compress(&setup).unwrap().len(), 22);
+        assert_eq!(compress_as(&setup, Format::BinaryFen).unwrap().len(), 24);
+    }
+
+    #[test]
+    fn invalid_code() {
+        let mut bytes = compress(&Setup::default()).unwrap();
+        bytes[0] = 0b1111_1100;
+        assert!(matches!(
+            decompress(&bytes),
+            Err(DecompressError::InvalidCode(Square::A1))
+        ));
+        assert!(matches!(decompress(&[]), Err(DecompressError::IO(_))));
+
+        let mut bytes = compress(&Setup::default()).unwrap();
+        *bytes.last_mut().unwrap() |= 1;
+        assert!(matches!(
+            decompress(&bytes),
+            Err(DecompressError::NonZeroPadding)
+        ));
+        let mut bytes = compress(&Setup::default()).unwrap();
+        bytes.push(0);
+        assert!(matches!(
+            decompress(&bytes),
+            Err(DecompressError::TrailingBytes(1))
+        ));
+
+        // An empty board with a halfmove clock of zero and a fullmove
+        // number of 2^32.
+        let bits = format!("{}11{}1{}0", "0".repeat(64), "0".repeat(32), "0".repeat(32));
+        let bytes: Vec<u8> = format!("{bits:0<136}")
+            .as_bytes()
+            .chunks(8)
+            .map(|byte| u8::from_str_radix(std::str::from_utf8(byte).unwrap(), 2).unwrap())
+            .collect();
+        assert!(matches!(
+            decompress(&bytes),
+            Err(DecompressError::Fullmoves(4294967296))
+        ));
+    }
+
+    #[test]
+    fn variant_fields() {
+        // Like BinaryFen, the compact format refuses what it can't store.
+        let crazyhouse = setup("8/8/8/8/8/8/8/8[Nq] w - - 0 1");
+        let three_check = setup("8/8/8/8/8/8/8/8 w - - 3+3 0 1");
+        for format in [Format::BinaryFen, Format::Compact] {
+            assert!(matches!(
+                compress_as(&crazyhouse, format),
+                Err(CompressError::Pockets(Variant::Standard))
+            ));
+            assert!(matches!(
+                compress_as(&three_check, format),
+                Err(CompressError::RemainingChecks(Variant::Standard))
+            ));
+        }
+        let mut promoted = setup("8/8/8/8/8/8/8/Q7 w - - 0 1");
+        promoted.promoted = Bitboard::from(Square::A1);
+        assert!(matches!(
+            compress(&promoted),
+            Err(CompressError::Pockets(Variant::Standard))
+        ));
+    }
+
+    #[test]
+    fn corpus_ratio() {
+        let (mut binary_fen, mut compact) = (0, 0);
+        for setup in corpus_positions().flatten() {
+            binary_fen += compress_as(&setup, Format::BinaryFen).unwrap().len();
+            let bytes = compress_as(&setup, Format::Compact).unwrap();
+            assert_eq!(decompress_as(&bytes, Format::Compact).unwrap(), setup);
+            compact += bytes.len();
+        }
+        println!(
+            "BinaryFen: {binary_fen} bytes, compact: {compact} bytes, ratio {:.3}",
+            compact as f64 / binary_fen as f64
+        );
+        assert!(compact < binary_fen);
+    }
+}
+
+mod test_position_delta {
+    use super::test_move_compression::corpus_positions;
+    use super::test_position_compression::setup;
+    use crate::delta::{compress, decompress, DecompressError};
+    use crate::position;
+    use shakmaty::{Chess, EnPassantMode, Position, Setup};
+
+    #[test]
+    fn single_move() {
+        let reference = Setup::default();
+        let position = setup("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1");
+        let bytes = compress(&position, &reference).unwrap();
+        // Flags, count, e2 (now empty) and e4, and one nibble for the pushed
+        // pawn.
+        assert_eq!(bytes, [0b0011, 2, 12 | 64, 28, 12]);
+        assert_eq!(decompress(&bytes, &reference).unwrap(), position);
+        assert_eq!(decompress(&[0b0001, 0], &reference).unwrap(), reference);
+    }
+
+    #[test]
+    fn fallback() {
+        let reference = Setup::empty();
+        let position = Setup::default();
+        let bytes = compress(&position, &reference).unwrap();
+        assert_eq!(bytes[0], 0);
+        assert_eq!(bytes[1..], position::compress(&position).unwrap());
+        assert_eq!(decompress(&bytes, &reference).unwrap(), position);
+    }
+
+    #[test]
+    fn clocks() {
+        let reference = setup("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 17 42");
+        let position = setup("r3k2r/8/8/8/8/8/8/R4RK1 b kq - 18 42");
+        let bytes = compress(&position, &reference).unwrap();
+        assert_eq!(decompress(&bytes, &reference).unwrap(), position);
+
+        let position = setup("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 300");
+        let bytes = compress(&position, &reference).unwrap();
+        assert_eq!(bytes, [0b1111, 0, 0, 172, 2]);
+        assert_eq!(decompress(&bytes, &reference).unwrap(), position);
+    }
+
+    #[test]
+    fn errors() {
+        let reference = Setup::default();
+        assert!(matches!(
+            decompress(&[], &reference),
+            Err(DecompressError::MissingBytes)
+        ));
+        assert!(matches!(
+            decompress(&[0b0010, 0], &reference),
+            Err(DecompressError::Flags(0b0010))
+        ));
+        assert!(matches!(
+            decompress(&[0b0001, 65], &reference),
+            Err(DecompressError::Changes(65))
+        ));
+        assert!(matches!(
+            decompress(&[0b0001, 1, 12], &reference),
+            Err(DecompressError::MissingBytes)
+        ));
+        assert!(matches!(
+            decompress(&[0b0001, 1, 0xc0], &reference),
+            Err(DecompressError::InvalidChange(0xc0))
+        ));
+        assert!(matches!(
+            decompress(&[0b1001, 0, 0], &reference),
+            Err(DecompressError::Fullmoves(0))
+        ));
+        assert!(matches!(
+            decompress(&[0b0001, 0, 0], &reference),
+            Err(DecompressError::TrailingBytes(1))
+        ));
+    }
+
+    #[test]
+    fn corpus() {
+        let (mut full, mut delta) = (0, 0);
+        for game in corpus_positions() {
+            let mut reference = Chess::default().to_setup(EnPassantMode::Legal);
+            for setup in game {
+                let bytes = compress(&setup, &reference).unwrap();
+                assert_eq!(decompress(&bytes, &reference).unwrap(), setup);
+                full += position::compress(&setup).unwrap().len();
+                delta += bytes.len();
+                reference = setup;
+            }
+        }
+        assert!(delta * 3 < full);
+    }
+}
+
+mod test_position_compression {
+    use crate::position::{
+        compress, compress_fen, compress_inline, compress_into, compress_position,
+        compress_variant, compress_variant_inline, decompress, decompress_chess, decompress_from,
+        decompress_position, decompress_strict, decompress_to_fen, decompress_variant,
+        CompressError, CompressedPositionView, ConvertError, DecompressError, Framing, Variant,
+        MAX_LEN,
+    };
+    use shakmaty::{
+        fen::{Fen, LossyFenErrorKinds},
+        Bitboard, CastlingMode, Chess, Color, EnPassantMode, Piece, Position, PositionErrorKinds,
+        Role, Setup, Square,
+    };
+    use std::collections::HashMap;
+    use std::num::NonZero;
+
+    pub(super) fn setup(fen: &str) -> Setup {
+        Fen::from_ascii(fen.as_bytes()).unwrap().into_setup()
+    }
 
     #[test]
     fn test_roundtrips() {
@@ -361,9 +1352,326 @@ mod test_position_compression {
         println!("{fen}");
         let position = Fen::from_ascii(fen.as_bytes()).unwrap().into_setup();
 
-        let roundtrip = decompress(&compress(&position).unwrap()).unwrap();
+        let compressed = compress(&position).unwrap();
+        assert_eq!(compressed, compress_inline(&position).unwrap().as_ref());
+        let roundtrip = decompress(&compressed).unwrap();
+        assert_eq!(
+            (roundtrip.clone(), Variant::Standard),
+            decompress_strict(&compressed).unwrap()
+        );
 
         assert_eq!(fen, format!("{}", Fen::try_from_setup(roundtrip).unwrap()));
+        assert_eq!(fen, decompress_to_fen(&compress_fen(fen).unwrap()).unwrap());
+    }
+
+    #[test]
+    fn test_conversions() {
+        let compressed = compress_position(&Chess::default()).unwrap();
+        assert_eq!(
+            compressed,
+            compress(&Chess::default().to_setup(EnPassantMode::Legal)).unwrap()
+        );
+        assert_eq!(
+            Chess::default(),
+            decompress_position::<Chess>(&compressed, CastlingMode::Standard).unwrap()
+        );
+
+        assert!(matches!(
+            compress_fen("not a fen"),
+            Err(ConvertError::Fen(_))
+        ));
+        let empty = compress_fen("8/8/8/8/8/8/8/8 w - - 0 1").unwrap();
+        assert!(matches!(
+            decompress_position::<Chess>(&empty, CastlingMode::Standard),
+            Err(ConvertError::Illegal(e)) if e.kinds().contains(PositionErrorKinds::EMPTY_BOARD)
+        ));
+        // The error keeps the position, so it can still be recovered.
+        let knights = compress_fen("4k3/8/8/8/8/8/NNNNNNNN/NNNNKNNN w - - 0 1").unwrap();
+        let Err(ConvertError::Illegal(e)) =
+            decompress_position::<Chess>(&knights, CastlingMode::Standard)
+        else {
+            panic!("expected an illegal position");
+        };
+        assert_eq!(e.kinds(), PositionErrorKinds::TOO_MUCH_MATERIAL);
+        assert_eq!(
+            e.ignore_too_much_material()
+                .unwrap()
+                .board()
+                .knights()
+                .count(),
+            15
+        );
+        assert!(matches!(
+            decompress_to_fen(&[0]),
+            Err(ConvertError::Decompress(DecompressError::MissingBytes))
+        ));
+
+        // Three white rooks with castling rights.
+        let rooks = hex::decode("0000000000000007dd0d").unwrap();
+        assert!(matches!(
+            decompress_to_fen(&rooks),
+            Err(ConvertError::LossyFen(LossyFenErrorKinds::CASTLING_RIGHTS))
+        ));
+    }
+
+    #[test]
+    fn test_view() {
+        let fens = [
+            "8/8/8/8/8/8/8/8 w - - 0 1",
+            "8/8/8/8/8/8/8/8 b - - 100 432",
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1",
+            "4nrk1/1pp3pp/p4p2/4P3/2BB1n2/8/PP3P1P/2K3R1 b - - 1 25",
+            "5k2/6p1/8/1Pp5/6P1/8/8/3K4 w - c6 0 1",
+            "4k3/8/8/8/3pP3/8/6N1/7K b - e3 0 1",
+            "8/8/8/4B2b/6nN/8/5P2/2R1K2k w Q - 1 1",
+        ];
+        for fen in fens {
+            let setup = Fen::from_ascii(fen.as_bytes()).unwrap().into_setup();
+            let compressed = compress_inline(&setup).unwrap();
+            let view = compressed.view();
+            assert_eq!(view.occupied(), setup.board.occupied());
+            for square in Square::ALL {
+                assert_eq!(view.piece_at(square), setup.board.piece_at(square));
+            }
+            assert_eq!(view.material(), setup.board.material());
+            assert_eq!(view.castling_rights(), setup.castling_rights);
+            assert_eq!(view.ep_square(), setup.ep_square);
+            assert_eq!(view.turn().unwrap(), setup.turn);
+            assert_eq!(view.halfmoves().unwrap(), setup.halfmoves);
+            assert_eq!(view.fullmoves().unwrap(), setup.fullmoves);
+            assert_eq!(view.variant().unwrap(), Variant::Standard);
+            assert_eq!(view.to_setup().unwrap(), setup);
+        }
+
+        let setup = Fen::from_ascii(b"8/8/8/8/8/8/8/8 w - - 0 1")
+            .unwrap()
+            .into_setup();
+        let atomic = compress_variant(&setup, Variant::Atomic).unwrap();
+        assert_eq!(
+            CompressedPositionView::new(&atomic)
+                .unwrap()
+                .variant()
+                .unwrap(),
+            Variant::Atomic
+        );
+        assert!(matches!(
+            CompressedPositionView::new(&hex::decode("0000000000000003").unwrap()),
+            Err(DecompressError::MissingBytes)
+        ));
+    }
+
+    #[test]
+    fn test_strict() {
+        // 5k2/6p1/8/1Pp5/6P1/8/8/3K4 w - c6 0 1, with the pieces altered.
+        let board = |pieces: &str| hex::decode(format!("2040000640000008{pieces}")).unwrap();
+        assert!(decompress_strict(&board("0ac0b1")).is_ok());
+        assert!(matches!(
+            decompress_strict(&board("0accb1")),
+            Err(DecompressError::DuplicateEnPassant(Square::C5))
+        ));
+        assert!(matches!(
+            decompress_strict(&board("0a00bc")),
+            Err(DecompressError::EnPassantRank(Square::G7))
+        ));
+        assert!(matches!(
+            decompress_strict(&board("0ac0be")),
+            Err(DecompressError::CastlingRook(Square::G7))
+        ));
+        assert!(matches!(
+            decompress_strict(&board("0ac0ff")),
+            Err(DecompressError::DuplicateTurnMarker(Square::F8))
+        ));
+        assert!(matches!(
+            decompress_strict(&board("0ac0f10002")),
+            Err(DecompressError::TurnMismatch)
+        ));
+        assert!(decompress(&board("0ac0f10002")).is_ok());
+        // A pushed pawn on g4 needs black to move, and one on c5 white.
+        assert!(decompress_strict(&board("ca10f1")).is_ok());
+        assert!(matches!(
+            decompress_strict(&board("ca10b1")),
+            Err(DecompressError::EnPassantTurn(Square::G4))
+        ));
+        assert!(matches!(
+            decompress_strict(&board("0ac0f1")),
+            Err(DecompressError::EnPassantTurn(Square::C5))
+        ));
+        // Clocks followed by the variant byte of standard chess, which
+        // compress never writes.
+        assert!(decompress(&board("0ac0b1000000")).is_ok());
+        assert!(matches!(
+            decompress_strict(&board("0ac0b1000000")),
+            Err(DecompressError::TrailingBytes(1))
+        ));
+
+        let padded = hex::decode("00000000000000011a").unwrap();
+        assert!(decompress(&padded).is_ok());
+        assert!(matches!(
+            decompress_strict(&padded),
+            Err(DecompressError::NonZeroPadding)
+        ));
+        let full = hex::decode(format!("ffffffffffffffff{}", "00".repeat(32))).unwrap();
+        assert!(matches!(
+            decompress_strict(&full),
+            Err(DecompressError::TooManyPieces(64))
+        ));
+    }
+
+    #[test]
+    fn test_hostile_trailers() {
+        let empty = |trailer: &[u64]| {
+            let mut bytes = vec![0; 8];
+            for value in trailer {
+                leb128::write::unsigned(&mut bytes, *value).unwrap();
+            }
+            bytes
+        };
+        assert!(matches!(
+            decompress(&empty(&[1 << 32])),
+            Err(DecompressError::Halfmoves(0x1_0000_0000))
+        ));
+        assert!(matches!(
+            decompress(&empty(&[0, u64::MAX])),
+            Err(DecompressError::PlyCount(u64::MAX))
+        ));
+        let max_ply = 2 * u64::from(u32::MAX - 1) + 1;
+        let setup = decompress(&empty(&[0, max_ply])).unwrap();
+        assert_eq!(setup.fullmoves.get(), u32::MAX);
+        assert_eq!(compress(&setup).unwrap(), empty(&[0, max_ply]));
+        assert!(matches!(
+            decompress(&empty(&[0, max_ply + 1])),
+            Err(DecompressError::PlyCount(_))
+        ));
+
+        // Black king to move but an even ply count.
+        let mut marked = hex::decode("00000000000000010f").unwrap();
+        marked.extend([0, 0]);
+        assert_eq!(decompress(&marked).unwrap().fullmoves.get(), 1);
+
+        let mut trailing = empty(&[0, 2, 0]);
+        trailing.push(0xff);
+        assert!(matches!(
+            decompress(&trailing),
+            Err(DecompressError::TrailingBytes(1))
+        ));
+    }
+
+    #[test]
+    fn test_inline() {
+        let fens = [
+            "8/8/8/8/8/8/8/8 w - - 0 1",
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1",
+            "4k3/8/8/8/3pP3/8/6N1/7K b - e3 0 1",
+        ];
+        let mut table = HashMap::new();
+        for (i, fen) in fens.iter().enumerate() {
+            let setup = Fen::from_ascii(fen.as_bytes()).unwrap().into_setup();
+            table.insert(compress_inline(&setup).unwrap(), i);
+        }
+        for (i, fen) in fens.iter().enumerate() {
+            let setup = Fen::from_ascii(fen.as_bytes()).unwrap().into_setup();
+            let bytes = compress(&setup).unwrap();
+            assert_eq!(table[bytes.as_slice()], i);
+        }
+
+        let mut keys: Vec<_> = table.keys().copied().collect();
+        keys.sort();
+        let mut slices: Vec<_> = keys.iter().map(|k| k.as_ref().to_vec()).collect();
+        slices.sort();
+        assert_eq!(
+            keys.iter().map(|k| k.as_ref().to_vec()).collect::<Vec<_>>(),
+            slices
+        );
+
+        // The longest possible encoding fills the buffer exactly.
+        let mut setup = Setup::empty();
+        for square in Bitboard::FULL {
+            let piece = Piece {
+                color: Color::White,
+                role: Role::Pawn,
+            };
+            setup.board.set_piece_at(square, piece);
+        }
+        setup.turn = Color::Black;
+        setup.halfmoves = u32::MAX;
+        setup.fullmoves = NonZero::new(u32::MAX).unwrap();
+        setup.promoted = Bitboard::FULL;
+        let longest = compress_variant_inline(&setup, Variant::Crazyhouse).unwrap();
+        assert_eq!(longest.as_ref().len(), MAX_LEN);
+        assert_eq!(
+            longest.as_ref(),
+            compress_variant(&setup, Variant::Crazyhouse).unwrap()
+        );
+        let (decoded, _) = decompress_variant(longest.as_ref()).unwrap();
+        assert_eq!(decoded.fullmoves, setup.fullmoves);
+    }
+
+    #[test]
+    fn test_streaming() {
+        let positions = [
+            ("8/8/8/8/8/8/8/8 w - - 0 1", Variant::Standard),
+            ("8/8/8/8/8/8/8/8 b - - 100 432", Variant::Standard),
+            (
+                "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1",
+                Variant::Chess960,
+            ),
+            (
+                "rnbqkb1r/ppp1pppp/5n2/3Q~4/8/8/PPPP1PPP/RNB1KBNR[Pp] b KQkq - 0 3",
+                Variant::Crazyhouse,
+            ),
+            (
+                "rnbqkbnr/ppp2ppp/8/1B1pp3/4P3/8/PPPP1PPP/RNBQK1NR b KQkq - 2+3 1 3",
+                Variant::ThreeCheck,
+            ),
+            ("8/8/8/8/8/8/8/8[Nq] w - - 0 1", Variant::Crazyhouse),
+        ];
+        for framing in [Framing::LengthPrefixed, Framing::FullTrailer] {
+            let mut stream = Vec::new();
+            for (fen, variant) in positions {
+                let setup = Fen::from_ascii(fen.as_bytes()).unwrap().into_setup();
+                compress_into(&mut stream, &setup, variant, framing).unwrap();
+            }
+            let mut input = stream.as_slice();
+            for (fen, variant) in positions {
+                let (setup, decoded) = decompress_from(&mut input, framing).unwrap().unwrap();
+                assert_eq!(variant, decoded);
+                assert_eq!(fen, format!("{}", Fen::try_from_setup(setup).unwrap()));
+            }
+            assert!(decompress_from(&mut input, framing).unwrap().is_none());
+
+            stream.pop();
+            let mut input = stream.as_slice();
+            for _i in 0..positions.len() - 1 {
+                decompress_from(&mut input, framing).unwrap().unwrap();
+            }
+            assert!(matches!(
+                decompress_from(&mut input, framing),
+                Err(DecompressError::IO(_))
+            ));
+        }
+        assert!(matches!(
+            decompress_from([0x80, 0x01].as_slice(), Framing::LengthPrefixed),
+            Err(DecompressError::Length(128))
+        ));
+    }
+
+    #[test]
+    fn test_chess() {
+        let start = Chess::default().to_setup(EnPassantMode::Legal);
+        assert_eq!(
+            Chess::default(),
+            decompress_chess(&compress(&start).unwrap(), CastlingMode::Standard).unwrap()
+        );
+        let empty = hex::decode("0000000000000000").unwrap();
+        assert!(matches!(
+            decompress_chess(&empty, CastlingMode::Standard),
+            Err(DecompressError::Position(_))
+        ));
+        let atomic = compress_variant(&start, Variant::Atomic).unwrap();
+        assert!(matches!(
+            decompress_chess(&atomic, CastlingMode::Standard),
+            Err(DecompressError::WrongVariant(Variant::Atomic))
+        ));
     }
 
     #[test]
@@ -385,6 +1693,98 @@ mod test_position_compression {
         );
     }
 
+    #[test]
+    fn test_variants() {
+        assert_variant_roundtrip(
+            Variant::Crazyhouse,
+            "r1bqk2r/ppp2ppp/2n5/3pP3/1b1Pn3/2N2N2/PPP2PPP/R1BQKB1R[PNpb] w KQkq - 0 8",
+        );
+        assert_variant_roundtrip(
+            Variant::Crazyhouse,
+            "rnbqkb1r/ppp1pppp/5n2/3Q~4/8/8/PPPP1PPP/RNB1KBNR[Pp] b KQkq - 0 3",
+        );
+        assert_variant_roundtrip(
+            Variant::ThreeCheck,
+            "rnbqkbnr/ppp2ppp/8/1B1pp3/4P3/8/PPPP1PPP/RNBQK1NR b KQkq - 2+3 1 3",
+        );
+        assert_variant_roundtrip(Variant::Chess960, "8/8/8/8/8/8/8/8 w - - 0 1");
+        assert_variant_roundtrip(Variant::Atomic, "8/8/8/8/8/8/8/8 b - - 0 1");
+
+        let position = Fen::from_ascii(b"8/8/8/8/8/8/8/8 w - - 0 1")
+            .unwrap()
+            .into_setup();
+        assert_eq!(
+            compress_variant(&position, Variant::Standard).unwrap(),
+            compress(&position).unwrap()
+        );
+        assert_eq!(
+            hex::encode(compress_variant(&position, Variant::Chess960).unwrap()),
+            "0000000000000000000002"
+        );
+        assert!(matches!(
+            decompress(&hex::decode("00000000000000000000ff").unwrap()),
+            Err(DecompressError::Variant(255))
+        ));
+        assert!(matches!(
+            decompress(&hex::decode("0000000000000000000005").unwrap()),
+            Err(DecompressError::MissingBytes)
+        ));
+        assert!(matches!(
+            decompress(&hex::decode("000000000000000000000534").unwrap()),
+            Err(DecompressError::RemainingChecks(4))
+        ));
+
+        // Like upstream, the promoted pieces are only written if there are
+        // any.
+        let position = Fen::from_ascii(b"8/8/8/8/8/8/8/8[Nq] w - - 0 1")
+            .unwrap()
+            .into_setup();
+        let bytes = compress_variant(&position, Variant::Crazyhouse).unwrap();
+        assert_eq!(hex::encode(&bytes), "00000000000000000000010001000010");
+        let (decoded, _) = decompress_variant(&bytes).unwrap();
+        assert_eq!(decoded, position);
+        assert!(matches!(
+            decompress(&hex::decode("0000000000000000000001000000000000000000").unwrap()),
+            Err(DecompressError::MissingBytes)
+        ));
+
+        // Fields the variant can't store are rejected, as upstream.
+        let crazyhouse = setup("8/8/8/8/8/8/8/8[Nq] w - - 0 1");
+        assert!(matches!(
+            compress(&crazyhouse),
+            Err(CompressError::Pockets(Variant::Standard))
+        ));
+        let three_check = setup("8/8/8/8/8/8/8/8 w - - 3+3 0 1");
+        assert!(matches!(
+            compress_variant(&three_check, Variant::Atomic),
+            Err(CompressError::RemainingChecks(Variant::Atomic))
+        ));
+        let mut promoted = setup("8/8/8/8/8/8/8/Q7[] w - - 0 1");
+        promoted.promoted = Bitboard::from(Square::B1);
+        assert!(matches!(
+            compress_variant(&promoted, Variant::Crazyhouse),
+            Err(CompressError::Promoted(squares)) if squares == Bitboard::from(Square::B1)
+        ));
+        assert!(matches!(
+            decompress(&hex::decode("000000000000000000000100000000000000000000000002").unwrap()),
+            Err(DecompressError::Promoted(_))
+        ));
+        let mut king = setup("8/8/8/8/8/8/8/8[] w - - 0 1");
+        king.pockets.as_mut().unwrap().black.king = 1;
+        assert!(matches!(
+            compress_variant(&king, Variant::Crazyhouse),
+            Err(CompressError::Pocket(_, 1))
+        ));
+    }
+
+    fn assert_variant_roundtrip(variant: Variant, fen: &str) {
+        let position = Fen::from_ascii(fen.as_bytes()).unwrap().into_setup();
+        let (roundtrip, decoded) =
+            decompress_variant(&compress_variant(&position, variant).unwrap()).unwrap();
+        assert_eq!(variant, decoded);
+        assert_eq!(fen, format!("{}", Fen::try_from_setup(roundtrip).unwrap()));
+    }
+
     fn assert_stability(fen: &str, encoded_hex: &str) {
         let position = Fen::from_ascii(fen.as_bytes()).unwrap().into_setup();
         let expected = hex::decode(encoded_hex).unwrap();
@@ -392,3 +1792,99 @@ mod test_position_compression {
         assert_eq!(compressed_position, expected);
     }
 }
+
+mod test_pgn {
+    use crate::moves::{compress_san, compress_san_from, decompress_from};
+    use crate::pgn::{pgn_reader::Reader, to_pgn, to_pgn_from, CompressingVisitor, ReadError};
+    use shakmaty::{fen::Fen, CastlingMode, Chess};
+
+    #[test]
+    fn test_checkmate() {
+        let sans = ["f3", "e5", "g4", "Qh4#"];
+        let compressed = compress_san(&sans).unwrap();
+        let pgn = to_pgn(compressed.as_slice(), 4, &[("White", "A \"B\" C")]).unwrap();
+        assert_eq!(
+            pgn,
+            "[White \"A \\\"B\\\" C\"]\n[Result \"0-1\"]\n\n1. f3 e5 2. g4 Qh4# 0-1\n\n"
+        );
+    }
+
+    #[test]
+    fn test_black_to_move() {
+        let fen = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1";
+        let position: Chess = Fen::from_ascii(fen.as_bytes())
+            .unwrap()
+            .into_position(CastlingMode::Standard)
+            .unwrap();
+        let compressed = compress_san_from(&["e5", "Nf3", "Nc6"], position.clone()).unwrap();
+        let pgn = to_pgn_from(compressed.as_slice(), 3, position, &[("Result", "*")]).unwrap();
+        assert_eq!(
+            pgn,
+            format!("[Result \"*\"]\n[SetUp \"1\"]\n[FEN \"{fen}\"]\n\n1... e5 2. Nf3 Nc6 *\n\n")
+        );
+    }
+
+    #[test]
+    fn test_line_length() {
+        let line = "Nf3 Nf6 Ng1 Ng8 ".repeat(20);
+        let sans: Vec<&str> = line.split_whitespace().collect();
+        let compressed = compress_san(&sans).unwrap();
+        let pgn = to_pgn(compressed.as_slice(), sans.len() as i32, &[]).unwrap();
+        assert!(pgn.lines().all(|line| line.len() <= 79));
+        assert!(pgn.ends_with(" *\n\n"));
+    }
+
+    #[test]
+    fn test_compressing_visitor() {
+        let pgn = b"[Event \"One\"]\n\n1. e4 e5 (1... c5 2. Nf3) 2. Nf3 Nc6 *\n\n\
+                    [Event \"Two\"]\n1. e4 e4 *\n\n\
+                    [SetUp \"1\"]\n[FEN \"4k3/8/8/8/8/8/8/4K2R w K - 0 1\"]\n\n1. O-O Kd7 *\n";
+        let mut reader = Reader::new(&pgn[..]);
+        let games: Vec<_> = reader
+            .read_games(&mut CompressingVisitor)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(games.len(), 3);
+
+        let first = games[0].as_ref().unwrap();
+        assert_eq!(first.tags, vec![("Event".to_string(), "One".to_string())]);
+        assert_eq!(first.plies, 4);
+        assert_eq!(
+            first.moves,
+            compress_san(&["e4", "e5", "Nf3", "Nc6"]).unwrap()
+        );
+
+        assert!(matches!(games[1], Err(ReadError::San(1, _))));
+
+        let third = games[2].as_ref().unwrap();
+        let position = third.position.clone().unwrap();
+        assert_eq!(
+            third.moves,
+            compress_san_from(&["O-O", "Kd7"], position.clone()).unwrap()
+        );
+        assert_eq!(
+            decompress_from(third.moves.as_slice(), third.plies, position)
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_unsupported_variant() {
+        let pgn = b"[Variant \"Crazyhouse\"]\n\n1. e4 d5 2. exd5 Qxd5 *\n\n\
+                    [Variant \"Chess960\"]\n\
+                    [FEN \"bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w HFhf - 0 1\"]\n\n\
+                    1. e4 *\n\n\
+                    [Variant \"From Position\"]\n\
+                    [FEN \"4k3/8/8/8/8/8/8/4K2R w K - 0 1\"]\n\n1. O-O *\n";
+        let mut reader = Reader::new(&pgn[..]);
+        let games: Vec<_> = reader
+            .read_games(&mut CompressingVisitor)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert!(matches!(&games[0], Err(ReadError::Variant(v)) if v == "Crazyhouse"));
+        assert_eq!(games[1].as_ref().unwrap().plies, 1);
+        assert_eq!(games[2].as_ref().unwrap().plies, 1);
+    }
+}
@@ -0,0 +1,249 @@
+//! Functions for compressing and decompressing trees of chess moves, such as
+//! studies or annotated games with variations.
+//!
+//! Moves are encoded with the same Huffman code as the [`crate::moves`]
+//! module, in pre-order: each move is followed by its subtree, with the
+//! mainline continuation first and sidelines after it. Before the moves
+//! following a position, a short marker gives the number of continuations:
+//!
+//! - `0`: exactly one continuation (the common case inside a line)
+//! - `10`: no continuations, returning to the last branch point
+//! - `11` followed by `n - 2` one bits and a zero bit: a branch into `n`
+//!   continuations
+//!
+//! Unlike a linear move sequence, the encoding is self-delimiting, so the
+//! number of plies does not need to be stored separately.
+
+use crate::moves::{read_move, write_move, CompressError, DecompressError};
+use bitbit::{BitReader, BitWriter, MSB};
+use shakmaty::{Chess, Move, Position};
+use std::io::{Read, Write};
+
+/// A move in a move tree, along with its continuations.
+///
+/// Trees can be as deep as the longest line, so dropping, cloning and
+/// comparing nodes walk the tree without recursion. Because of the manual
+/// [`Drop`], the fields can't be moved out by destructuring; take the
+/// children with [`std::mem::take`] instead. The derived [`Debug`] still
+/// recurses once per ply.
+#[derive(Debug, Eq)]
+pub struct Node {
+    /// The move played.
+    pub m: Move,
+    /// Continuations after the move. The first one, if any, is the mainline.
+    pub children: Vec<Node>,
+}
+
+impl Node {
+    /// Create a node without continuations.
+    pub fn new(m: Move) -> Self {
+        Node {
+            m,
+            children: Vec::new(),
+        }
+    }
+
+    /// Build a tree from a linear sequence of moves, returning `None` if the
+    /// sequence is empty.
+    pub fn from_line(moves: &[Move]) -> Option<Self> {
+        moves.iter().rev().fold(None, |child, m| {
+            Some(Node {
+                m: *m,
+                children: child.into_iter().collect(),
+            })
+        })
+    }
+
+    /// The mainline starting with this node's move.
+    pub fn mainline(&self) -> Vec<Move> {
+        let mut moves = vec![self.m];
+        let mut node = self;
+        while let Some(child) = node.children.first() {
+            moves.push(child.m);
+            node = child;
+        }
+        moves
+    }
+}
+
+/* The derived drop, clone and comparison recurse once per ply, which
+ * overflows the stack for very long lines. */
+
+impl Drop for Node {
+    fn drop(&mut self) {
+        let mut stack = std::mem::take(&mut self.children);
+        while let Some(mut node) = stack.pop() {
+            stack.append(&mut node.children);
+        }
+    }
+}
+
+impl Clone for Node {
+    fn clone(&self) -> Self {
+        let copy = |node: &Node| Node {
+            m: node.m,
+            children: Vec::with_capacity(node.children.len()),
+        };
+        let mut stack = vec![(self.children.iter(), copy(self))];
+        loop {
+            let (children, _) = stack.last_mut().unwrap();
+            if let Some(child) = children.next() {
+                stack.push((child.children.iter(), copy(child)));
+                continue;
+            }
+            let (_, node) = stack.pop().unwrap();
+            match stack.last_mut() {
+                Some((_, parent)) => parent.children.push(node),
+                None => return node,
+            }
+        }
+    }
+}
+
+impl PartialEq for Node {
+    fn eq(&self, other: &Self) -> bool {
+        let mut stack = vec![(self, other)];
+        while let Some((a, b)) = stack.pop() {
+            if a.m != b.m || a.children.len() != b.children.len() {
+                return false;
+            }
+            stack.extend(a.children.iter().zip(&b.children));
+        }
+        true
+    }
+}
+
+/// Compress a move tree from the starting position.
+///
+/// The roots are the alternative first moves, with the mainline first.
+pub fn compress(roots: &[Node]) -> Result<Vec<u8>, CompressError> {
+    compress_from(roots, Chess::default())
+}
+
+/// Compress a move tree from a given position.
+pub fn compress_from(roots: &[Node], position: Chess) -> Result<Vec<u8>, CompressError> {
+    let mut output = Vec::new();
+    let mut writer = BitWriter::new(&mut output);
+    write_children(roots, &position, &mut writer)?;
+    writer.pad_to_byte().map_err(CompressError::IO)?;
+    Ok(output)
+}
+
+/// Decompress a move tree from the starting position.
+pub fn decompress<R: Read>(input: R) -> Result<Vec<Node>, DecompressError> {
+    decompress_from(input, Chess::default())
+}
+
+/// Decompress a move tree from a given position.
+pub fn decompress_from<R: Read>(input: R, position: Chess) -> Result<Vec<Node>, DecompressError> {
+    let mut reader = BitReader::<_, MSB>::new(input);
+    read_children(&mut reader, &position)
+}
+
+/* Trees can be as deep as the longest line, and `play` doesn't enforce the
+ * fifty-move or repetition rules, so the tree is walked with explicit stacks
+ * rather than recursion to keep hostile input from overflowing the stack. */
+
+fn write_children<W: Write>(
+    roots: &[Node],
+    position: &Chess,
+    writer: &mut BitWriter<W>,
+) -> Result<(), CompressError> {
+    write_count(roots.len(), writer).map_err(CompressError::IO)?;
+    let mut stack = vec![(roots.iter(), position.clone())];
+    while let Some((children, position)) = stack.last_mut() {
+        let Some(child) = children.next() else {
+            stack.pop();
+            continue;
+        };
+        write_move(&child.m, position, writer)?;
+        let next = position
+            .clone()
+            .play(child.m)
+            .map_err(|e| CompressError::Chess(Box::new(e)))?;
+        write_count(child.children.len(), writer).map_err(CompressError::IO)?;
+        stack.push((child.children.iter(), next));
+    }
+    Ok(())
+}
+
+/// A node being read, with the number of continuations still to read.
+struct Frame {
+    m: Option<Move>,
+    position: Chess,
+    remaining: usize,
+    children: Vec<Node>,
+}
+
+impl Frame {
+    fn read<R: Read>(
+        reader: &mut BitReader<R, MSB>,
+        m: Option<Move>,
+        position: Chess,
+    ) -> Result<Self, DecompressError> {
+        let remaining = read_count(reader).map_err(DecompressError::IO)?;
+        Ok(Frame {
+            m,
+            position,
+            remaining,
+            children: Vec::with_capacity(remaining),
+        })
+    }
+}
+
+fn read_children<R: Read>(
+    reader: &mut BitReader<R, MSB>,
+    position: &Chess,
+) -> Result<Vec<Node>, DecompressError> {
+    let mut stack = vec![Frame::read(reader, None, position.clone())?];
+    while let Some(frame) = stack.last_mut() {
+        if frame.remaining == 0 {
+            let frame = stack.pop().unwrap();
+            match (frame.m, stack.last_mut()) {
+                (Some(m), Some(parent)) => parent.children.push(Node {
+                    m,
+                    children: frame.children,
+                }),
+                _ => return Ok(frame.children),
+            }
+            continue;
+        }
+        frame.remaining -= 1;
+        let m = read_move(reader, &frame.position)?;
+        let next = frame
+            .position
+            .clone()
+            .play(m)
+            .map_err(|e| DecompressError::Chess(Box::new(e)))?;
+        stack.push(Frame::read(reader, Some(m), next)?);
+    }
+    unreachable!("the root frame returns when it is complete")
+}
+
+fn write_count<W: Write>(count: usize, writer: &mut BitWriter<W>) -> std::io::Result<()> {
+    match count {
+        0 => writer.write_bits(0b10, 2),
+        1 => writer.write_bit(false),
+        n => {
+            writer.write_bits(0b11, 2)?;
+            for _i in 2..n {
+                writer.write_bit(true)?;
+            }
+            writer.write_bit(false)
+        }
+    }
+}
+
+fn read_count<R: Read>(reader: &mut BitReader<R, MSB>) -> std::io::Result<usize> {
+    if !reader.read_bit()? {
+        return Ok(1);
+    }
+    if !reader.read_bit()? {
+        return Ok(0);
+    }
+    let mut count = 2;
+    while reader.read_bit()? {
+        count += 1;
+    }
+    Ok(count)
+}